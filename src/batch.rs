@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use futures::Future;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::params::{BatchParams, JobKind, PostParams};
+use crate::routes::endpoint_value;
+use crate::uris::normalize_uri;
+
+const MAX_BATCH_URIS: usize = 500;
+const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_CONCURRENCY: usize = 16;
+
+// The result or error for one URI. `index` is its position in the request,
+// as items are reported in the order they finish
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchItem {
+  pub index: usize,
+  pub uri: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub result: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>,
+  pub valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResultSet {
+  pub endpoint: JobKind,
+  pub items: Vec<BatchItem>,
+  pub total: usize,
+  pub succeeded: usize,
+  pub failed: usize,
+  pub valid: bool,
+}
+
+impl BatchResultSet {
+  // Totals without the items, sent after a streamed batch and stored as a batch job's result
+  pub fn summary(&self) -> Value {
+    json!({
+      "endpoint": self.endpoint,
+      "total": self.total,
+      "succeeded": self.succeeded,
+      "failed": self.failed,
+      "valid": self.valid,
+    })
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchProgress {
+  pub done: usize,
+  pub total: usize,
+}
+
+pub struct BatchConfig {
+  pub endpoint: JobKind,
+  pub uris: Vec<String>,
+  pub concurrency: usize,
+  pub options: PostParams,
+  // indexes of items reported by an earlier run, which are skipped
+  pub completed: HashSet<usize>,
+}
+
+impl BatchConfig {
+  pub fn new(params: &BatchParams) -> Result<BatchConfig, String> {
+    let endpoint = params.endpoint.unwrap_or(JobKind::GetContentItems);
+    if !endpoint.is_single_page() {
+      return Err("batches only support single-page endpoints".to_owned());
+    }
+    if params.uris.is_empty() {
+      return Err("no uris".to_owned());
+    }
+    if params.uris.len() > MAX_BATCH_URIS {
+      return Err(format!("at most {} uris per batch", MAX_BATCH_URIS));
+    }
+    Ok(BatchConfig {
+      endpoint,
+      uris: params.uris.clone(),
+      concurrency: params.concurrency.unwrap_or(DEFAULT_CONCURRENCY).clamp(1, MAX_CONCURRENCY),
+      options: params.options.clone(),
+      completed: HashSet::new(),
+    })
+  }
+}
+
+async fn run_batch_item(index: usize, uri: String, config: &BatchConfig) -> BatchItem {
+  let full_uri = normalize_uri(&uri);
+  if !full_uri.starts_with("http") {
+    return BatchItem { index, uri, result: None, error: Some("invalid uri".to_owned()), valid: false };
+  }
+  let mut params = config.options.clone();
+  params.uri = Some(full_uri);
+  match endpoint_value(config.endpoint, &params).await {
+    // the item fails with the endpoint's own error, keeping the invalid body for its details
+    Some(result) if result.get("valid").and_then(Value::as_bool) == Some(false) => {
+      let error = result.get("error").and_then(Value::as_str).unwrap_or("page could not be fetched or parsed").to_owned();
+      BatchItem { index, uri, result: Some(result), error: Some(error), valid: false }
+    },
+    Some(result) => BatchItem { index, uri, result: Some(result), error: None, valid: true },
+    None => BatchItem { index, uri, result: None, error: Some("unsupported endpoint".to_owned()), valid: false }
+  }
+}
+
+// Run the endpoint for every URI with up to `concurrency` pages in flight.
+// `on_item` receives each item as it finishes and may return false to stop the batch
+pub async fn run_batch<F, Fut>(config: &BatchConfig, mut on_item: F) -> BatchResultSet
+where F: FnMut(&BatchItem, BatchProgress) -> Fut, Fut: Future<Output = bool> {
+  let total = config.uris.len();
+  let mut results = stream::iter(config.uris.iter().cloned().enumerate())
+    .filter(|(index, _)| futures::future::ready(!config.completed.contains(index)))
+    .map(|(index, uri)| run_batch_item(index, uri, config))
+    .buffer_unordered(config.concurrency);
+  let mut items: Vec<BatchItem> = vec![];
+  while let Some(item) = results.next().await {
+    let progress = BatchProgress { done: config.completed.len() + items.len() + 1, total };
+    let keep_going = on_item(&item, progress).await;
+    items.push(item);
+    if !keep_going {
+      break;
+    }
+  }
+  items.sort_by_key(|item| item.index);
+  let succeeded = items.iter().filter(|item| item.valid).count();
+  BatchResultSet {
+    endpoint: config.endpoint,
+    failed: items.len() - succeeded,
+    total,
+    succeeded,
+    valid: succeeded > 0,
+    items,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::batch::BatchConfig;
+  use crate::params::{BatchParams, JobKind};
+
+  #[test]
+  fn test_batch_config_defaults_and_clamping() {
+    let params: BatchParams = serde_json::from_value(serde_json::json!({
+      "uris": ["https://example.com/a", "https://example.com/b"],
+      "items": [{ "kind": "data", "path": "h1" }],
+      "concurrency": 64
+    })).unwrap();
+    let config = BatchConfig::new(&params).unwrap();
+    assert_eq!(config.endpoint, JobKind::GetContentItems);
+    assert_eq!(config.concurrency, 16);
+    assert_eq!(config.options.items.as_ref().map(|items| items.len()), Some(1));
+  }
+
+  #[test]
+  fn test_batch_rejects_multi_page_endpoints() {
+    let crawl: BatchParams = serde_json::from_value(serde_json::json!({ "uris": ["https://example.com"], "endpoint": "crawl" })).unwrap();
+    assert!(BatchConfig::new(&crawl).is_err());
+  }
+}
@@ -0,0 +1,282 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Attr, Name, Predicate};
+use serde::Serialize;
+use serde_json::Value;
+use string_patterns::*;
+use crate::metadata::PageMetadata;
+use crate::cleantext::collapse_whitespace;
+
+const ARTICLE_TYPES: [&str; 12] = ["Article", "NewsArticle", "BlogPosting", "LiveBlogPosting", "Report", "ScholarlyArticle", "TechArticle", "OpinionNewsArticle", "AnalysisNewsArticle", "ReportageNewsArticle", "Blog", "WebPage"];
+const PUBLISHED_META: [(&str, &str, Confidence); 8] = [
+  ("property", "article:published_time", Confidence::High),
+  ("itemprop", "datePublished", Confidence::High),
+  ("name", "parsely-pub-date", Confidence::Medium),
+  ("name", "pubdate", Confidence::Medium),
+  ("name", "publishdate", Confidence::Medium),
+  ("name", "dc.date.issued", Confidence::Medium),
+  ("name", "sailthru.date", Confidence::Medium),
+  ("name", "date", Confidence::Medium),
+];
+const MODIFIED_META: [(&str, &str, Confidence); 4] = [
+  ("property", "article:modified_time", Confidence::High),
+  ("itemprop", "dateModified", Confidence::High),
+  ("property", "og:updated_time", Confidence::Medium),
+  ("name", "last-modified", Confidence::Medium),
+];
+const BYLINE_CLASS_HINTS: [&str; 6] = ["byline", "author-name", "author", "writer", "contributor", "posted-by"];
+const DATE_FORMATS: [&str; 6] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M", "%Y%m%dT%H%M%S"];
+const DAY_FORMATS: [&str; 9] = ["%Y-%m-%d", "%Y/%m/%d", "%d %B %Y", "%d %b %Y", "%B %d %Y", "%b %d %Y", "%B %d, %Y", "%b %d, %Y", "%Y%m%d"];
+const MONTH_PATTERN: &str = r"(?:jan(?:uary)?|feb(?:ruary)?|mar(?:ch)?|apr(?:il)?|may|june?|july?|aug(?:ust)?|sep(?:t(?:ember)?)?|oct(?:ober)?|nov(?:ember)?|dec(?:ember)?)";
+// characters of text to scan after the main heading for bylines and dates
+const NEAR_TITLE_WINDOW: usize = 400;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Confidence {
+  #[serde(rename = "low")]
+  Low,
+  #[serde(rename = "medium")]
+  Medium,
+  #[serde(rename = "high")]
+  High
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DateMatch {
+  pub value: String,
+  pub source: String,
+  pub confidence: Confidence,
+}
+
+impl DateMatch {
+  fn new(raw: &str, source: &str, confidence: Confidence) -> Option<DateMatch> {
+    normalize_date(raw).map(|value| DateMatch { value, source: source.to_string(), confidence })
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BylineMatch {
+  pub names: Vec<String>,
+  pub source: String,
+  pub confidence: Confidence,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArticleInfo {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub byline: Option<BylineMatch>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub published: Option<DateMatch>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub modified: Option<DateMatch>,
+}
+
+impl ArticleInfo {
+  pub fn new(doc: &Document, metadata: &PageMetadata) -> ArticleInfo {
+    let article_ld = metadata.json_ld_of_type(&ARTICLE_TYPES);
+    let near_title = extract_text_near_title(doc);
+    let published = date_from_json_ld(&article_ld, "datePublished")
+      .or_else(|| date_from_meta(doc, &PUBLISHED_META))
+      .or_else(|| date_from_time_tags(doc))
+      .or_else(|| date_from_text(&near_title));
+    let modified = date_from_json_ld(&article_ld, "dateModified")
+      .or_else(|| date_from_meta(doc, &MODIFIED_META));
+    let byline = byline_from_json_ld(&article_ld)
+      .or_else(|| byline_from_meta(doc))
+      .or_else(|| byline_from_markup(doc))
+      .or_else(|| byline_from_text(&near_title));
+    ArticleInfo {
+      byline,
+      published,
+      modified,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.byline.is_none() && self.published.is_none() && self.modified.is_none()
+  }
+}
+
+// Normalise common machine and human readable date formats to ISO 8601
+pub fn normalize_date(raw: &str) -> Option<String> {
+  let text = raw.trim();
+  if text.is_empty() {
+    return None;
+  }
+  if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+    return Some(dt.to_rfc3339());
+  }
+  if let Ok(dt) = DateTime::parse_from_rfc2822(text) {
+    return Some(dt.to_rfc3339());
+  }
+  if let Ok(dt) = DateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%z") {
+    return Some(dt.to_rfc3339());
+  }
+  for fmt in DATE_FORMATS {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(text, fmt) {
+      return Some(dt.format("%Y-%m-%dT%H:%M:%S").to_string());
+    }
+  }
+  let day_text = text.to_string().pattern_replace(r"(\d)(st|nd|rd|th)\b", "$1", true).replace('.', "");
+  let day_text = collapse_whitespace(&day_text);
+  for fmt in DAY_FORMATS {
+    if let Ok(d) = NaiveDate::parse_from_str(&day_text, fmt) {
+      return Some(d.format("%Y-%m-%d").to_string());
+    }
+  }
+  None
+}
+
+fn clean_name(name: &str) -> Option<String> {
+  let name = collapse_whitespace(name);
+  let name = name.pattern_replace(r"^(written\s+)?by\s+", "", true);
+  let name = name.trim_matches(|c: char| c == ',' || c == '|' || c.is_whitespace()).to_string();
+  let is_uri = name.starts_with("http://") || name.starts_with("https://");
+  if name.len() > 1 && name.len() < 80 && !is_uri {
+    Some(name)
+  } else {
+    None
+  }
+}
+
+fn to_byline(names: Vec<String>, source: &str, confidence: Confidence) -> Option<BylineMatch> {
+  let mut unique: Vec<String> = vec![];
+  for name in names.iter().filter_map(|n| clean_name(n)) {
+    if !unique.contains(&name) {
+      unique.push(name);
+    }
+  }
+  if !unique.is_empty() {
+    Some(BylineMatch { names: unique, source: source.to_string(), confidence })
+  } else {
+    None
+  }
+}
+
+fn date_from_json_ld(items: &[&Value], field: &str) -> Option<DateMatch> {
+  items.iter()
+    .filter_map(|item| item.get(field).and_then(|v| v.as_str()))
+    .find_map(|raw| DateMatch::new(raw, "json_ld", Confidence::High))
+}
+
+fn date_from_meta(doc: &Document, rules: &[(&str, &str, Confidence)]) -> Option<DateMatch> {
+  for (ref_field, name, confidence) in rules {
+    let matched = doc.find(Attr(*ref_field, ())).find(|el| el.attr(ref_field).map(|v| v.eq_ignore_ascii_case(name)).unwrap_or(false));
+    if let Some(element) = matched {
+      let raw = element.attr("content").or(element.attr("datetime")).unwrap_or("");
+      if let Some(dm) = DateMatch::new(raw, "meta", *confidence) {
+        return Some(dm);
+      }
+    }
+  }
+  None
+}
+
+fn date_from_time_tags(doc: &Document) -> Option<DateMatch> {
+  let time_tags = doc.find(Name("time").and(Attr("datetime", ()))).collect::<Vec<Node>>();
+  // a pubdate attribute marks the publication date explicitly
+  let preferred = time_tags.iter().find(|el| el.attr("pubdate").is_some()).or(time_tags.first());
+  preferred.and_then(|el| {
+    let confidence = if el.attr("pubdate").is_some() { Confidence::High } else { Confidence::Medium };
+    DateMatch::new(el.attr("datetime").unwrap_or(""), "time", confidence)
+  })
+}
+
+fn date_from_text(text: &str) -> Option<DateMatch> {
+  let patterns = [
+    r"\b\d{4}-\d{2}-\d{2}\b".to_string(),
+    [r"\b\d{1,2}(?:st|nd|rd|th)?\s+", MONTH_PATTERN, r"\.?,?\s+\d{4}\b"].concat(),
+    [r"\b", MONTH_PATTERN, r"\.?\s+\d{1,2}(?:st|nd|rd|th)?,?\s+\d{4}\b"].concat(),
+  ];
+  patterns.iter()
+    .filter_map(|pat| text.pattern_first_match(pat, true))
+    .find_map(|m| DateMatch::new(m.as_str(), "text", Confidence::Low))
+}
+
+fn json_ld_names(value: &Value) -> Vec<String> {
+  match value {
+    Value::String(name) => vec![name.to_owned()],
+    Value::Array(values) => values.iter().flat_map(json_ld_names).collect(),
+    Value::Object(obj) => obj.get("name").map(json_ld_names).unwrap_or_default(),
+    _ => vec![]
+  }
+}
+
+fn byline_from_json_ld(items: &[&Value]) -> Option<BylineMatch> {
+  let names = items.iter().filter_map(|item| item.get("author")).flat_map(json_ld_names).collect::<Vec<String>>();
+  to_byline(names, "json_ld", Confidence::High)
+}
+
+fn byline_from_meta(doc: &Document) -> Option<BylineMatch> {
+  let names = doc.find(Name("meta"))
+    .filter(|el| el.attr("name").or(el.attr("property")).map(|n| n.eq_ignore_ascii_case("author") || n.eq_ignore_ascii_case("article:author")).unwrap_or(false))
+    .filter_map(|el| el.attr("content").map(|c| c.to_string()))
+    .collect::<Vec<String>>();
+  to_byline(names, "meta", Confidence::High)
+}
+
+fn has_byline_hint(node: &Node) -> bool {
+  let hints = [node.attr("class").unwrap_or(""), node.attr("id").unwrap_or("")].join(" ").to_lowercase();
+  hints.split_whitespace().any(|token| BYLINE_CLASS_HINTS.iter().any(|hint| token == *hint || token.ends_with(&["-", hint].concat()) || token.starts_with(&[hint, "-"].concat()) || token.starts_with(&[hint, "_"].concat())))
+}
+
+fn byline_from_markup(doc: &Document) -> Option<BylineMatch> {
+  let rel_names = doc.find(Name("a").and(Attr("rel", "author"))).map(|el| el.text()).collect::<Vec<String>>();
+  if let Some(bm) = to_byline(rel_names, "rel_author", Confidence::Medium) {
+    return Some(bm);
+  }
+  let prop_names = doc.find(Attr("itemprop", "author")).map(|el| {
+    el.find(Attr("itemprop", "name")).next().map(|n| n.attr("content").map(|c| c.to_string()).unwrap_or(n.text())).unwrap_or(el.text())
+  }).collect::<Vec<String>>();
+  if let Some(bm) = to_byline(prop_names, "itemprop", Confidence::Medium) {
+    return Some(bm);
+  }
+  let class_names = doc.find(|el: &Node| el.name().is_some() && has_byline_hint(el))
+    .map(|el| el.find(Name("a")).next().map(|a| a.text()).unwrap_or(el.text()))
+    .take(3)
+    .collect::<Vec<String>>();
+  to_byline(class_names, "class", Confidence::Medium)
+}
+
+fn byline_from_text(text: &str) -> Option<BylineMatch> {
+  text.pattern_captures(r"\bby\s+([A-Z][\p{L}'.-]+(?:\s+[A-Z][\p{L}'.-]+){0,3})", false)
+    .and_then(|caps| caps.get(1).map(|m| m.as_str().to_string()))
+    .and_then(|name| to_byline(vec![name], "text", Confidence::Low))
+}
+
+// Text following the first h1 within its container, where bylines and dates usually appear
+fn extract_text_near_title(doc: &Document) -> String {
+  if let Some(heading) = doc.find(Name("h1")).next() {
+    let title = collapse_whitespace(&heading.text());
+    let container = heading.parent().unwrap_or(heading);
+    let text = collapse_whitespace(&container.text());
+    let start = text.find(&title).map(|pos| pos + title.len()).unwrap_or(0);
+    text[start..].chars().take(NEAR_TITLE_WINDOW).collect()
+  } else {
+    "".to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::byline::normalize_date;
+
+  #[test]
+  fn test_normalize_machine_dates() {
+    assert_eq!(normalize_date("2023-03-12T08:30:00+01:00"), Some("2023-03-12T08:30:00+01:00".to_string()));
+    assert_eq!(normalize_date("Sun, 12 Mar 2023 08:30:00 GMT"), Some("2023-03-12T08:30:00+00:00".to_string()));
+    assert_eq!(normalize_date("2023-03-12 08:30:00"), Some("2023-03-12T08:30:00".to_string()));
+  }
+
+  #[test]
+  fn test_normalize_written_dates() {
+    assert_eq!(normalize_date("12th March 2023"), Some("2023-03-12".to_string()));
+    assert_eq!(normalize_date("Mar. 12, 2023"), Some("2023-03-12".to_string()));
+  }
+
+  #[test]
+  fn test_relative_dates_are_rejected() {
+    assert_eq!(normalize_date("last week"), None);
+  }
+}
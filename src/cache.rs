@@ -1,4 +1,4 @@
-use redis::{Commands, RedisResult, Connection, Client};
+use redis::{aio, Commands, RedisResult, Connection, Client};
 use chrono::{Local, Duration};
 use serde::{Serialize, Deserialize};
 
@@ -7,6 +7,12 @@ pub fn  redis_client() -> RedisResult<Connection> {
   client.get_connection()
 }
 
+// For long-running tasks that write to redis from within the runtime
+pub async fn redis_async_client() -> RedisResult<aio::Connection> {
+  let client = Client::open("redis://127.0.0.1/")?;
+  client.get_async_connection().await
+}
+
 pub fn get_timestamp() -> i64 {
   let dt = Local::now();
   dt.timestamp()
@@ -26,6 +32,13 @@ fn redis_get_opt_string(key: &str) -> Option<String> {
   }
 }
 
+pub const HTML_CONTENT_TYPE: &str = "text/html";
+
+// pages cached before content types were recorded are HTML
+fn default_content_type() -> String {
+  HTML_CONTENT_TYPE.to_owned()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlatPage {
   pub uri: String,
@@ -33,6 +46,8 @@ pub struct FlatPage {
   pub ts: i64,
   pub cached: bool,
   pub full_browser: bool,
+  #[serde(default = "default_content_type")]
+  pub content_type: String,
 }
 
 impl FlatPage {
@@ -42,17 +57,25 @@ impl FlatPage {
       content: content.to_string(),
       ts: get_timestamp(),
       cached: false,
-      full_browser
+      full_browser,
+      content_type: default_content_type()
     }
   }
 
+  // Media type without parameters such as charset
+  pub fn with_content_type(mut self, content_type: &str) -> Self {
+    self.content_type = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    self
+  }
+
   pub fn empty() -> Self {
     FlatPage { 
       uri: "".to_string(),
       content: "".to_string(),
       ts: 0,
       cached: false,
-      full_browser: false
+      full_browser: false,
+      content_type: default_content_type()
     }
   }
 
@@ -72,17 +95,18 @@ impl FlatPage {
 }
 
 pub fn  redis_set_page(key: &str, uri: &str, content: &str, full_browser: bool) -> Option<FlatPage> {
+  let stored_object = FlatPage::new(uri, content, full_browser);
+  redis_store_page(key, &stored_object).then_some(stored_object)
+}
+
+pub fn redis_store_page(key: &str, page: &FlatPage) -> bool {
   if let Ok(mut connection) =  redis_client() {
-      let stored_object = FlatPage::new(uri, content, full_browser);
-      match serde_json::to_string(&stored_object) {
-        Ok(value) => match connection.set::<String,String,String>(key.to_string(), value) {
-          Ok(_result) => Some(stored_object),
-          Err(_error) => None,
-        },
-        Err(_error) => None,
+      match serde_json::to_string(page) {
+        Ok(value) => connection.set::<String,String,String>(key.to_string(), value).is_ok(),
+        Err(_error) => false,
       }
   } else {
-    None
+    false
   }
 }
 
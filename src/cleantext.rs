@@ -17,4 +17,9 @@ pub fn clean_raw_html(html: &str) -> String {
 
 pub fn strip_literal_tags(text: &str) -> String {
   text.to_owned().pattern_replace(r"</?\w[^>]*?>", "", true).trim().to_owned()
-}
\ No newline at end of file
+}
+
+// Runs of whitespace, including line breaks, become single spaces
+pub fn collapse_whitespace(text: &str) -> String {
+  text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
@@ -0,0 +1,348 @@
+use std::collections::HashSet;
+use futures::Future;
+use futures::stream::{self, StreamExt};
+use scraper::Html;
+use serde::Serialize;
+use serde_json::{json, Value};
+use string_patterns::{Regex, build_regex};
+use crate::cache::FlatPage;
+use crate::cleantext::clean_raw_html;
+use crate::links::extract_links;
+use crate::page_data::{ContentOptions, ContentResultSet, PageResultSet, ShowMode, build_content_data, build_content_items, fetch_page, is_html_page};
+use crate::params::{CrawlMode, CrawlParams, TargetConfig};
+use crate::stats::{extract_base_uri, is_local_uri};
+use crate::uris::normalize_uri;
+
+const DEFAULT_CRAWL_DEPTH: u8 = 1;
+const MAX_CRAWL_DEPTH: u8 = 6;
+const DEFAULT_CRAWL_PAGES: usize = 50;
+const MAX_CRAWL_PAGES: usize = 1000;
+const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_CONCURRENCY: usize = 16;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum CrawlExtract {
+  Page(Box<PageResultSet>),
+  Items(Box<ContentResultSet>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlPage {
+  pub uri: String,
+  pub depth: u8,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub parent: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub data: Option<CrawlExtract>,
+  #[serde(rename = "numLinks")]
+  pub num_links: usize,
+  pub valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlResultSet {
+  pub seed: String,
+  pub pages: Vec<CrawlPage>,
+  pub visited: usize,
+  // in-scope links left in the queue when the page limit was reached
+  pub remaining: usize,
+  pub valid: bool,
+}
+
+// Pages visited so far and the estimated total given the queue and page limit
+#[derive(Debug, Clone, Copy)]
+pub struct CrawlProgress {
+  pub visited: usize,
+  pub total: usize,
+}
+
+impl CrawlResultSet {
+  // Seed and visit counts, with the remaining queue, that close a streamed crawl or a crawl job
+  pub fn summary(&self) -> Value {
+    json!({
+      "seed": self.seed,
+      "visited": self.visited,
+      "remaining": self.remaining,
+      "valid": self.valid,
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct CrawlTarget {
+  pub uri: String,
+  pub depth: u8,
+  pub parent: Option<String>,
+}
+
+// Validated crawl settings shared by all workers
+pub struct CrawlConfig {
+  pub seed: String,
+  // the seed's scheme and host, which same-domain links must share
+  pub base_uri: String,
+  pub mode: CrawlMode,
+  pub max_depth: u8,
+  pub max_pages: usize,
+  pub concurrency: usize,
+  pub same_domain: bool,
+  pub subdomains: bool,
+  pub skip_cache: bool,
+  pub include: Vec<Regex>,
+  pub exclude: Vec<Regex>,
+  pub target: Option<String>,
+  pub show_links: bool,
+  pub targets: Vec<String>,
+  pub items: Vec<TargetConfig>,
+  pub options: ContentOptions,
+}
+
+fn compile_patterns(patterns: &Option<Vec<String>>) -> Vec<Regex> {
+  patterns.clone().unwrap_or_default().iter().filter_map(|pat| build_regex(pat, true).ok()).collect()
+}
+
+impl CrawlConfig {
+  pub fn new(params: &CrawlParams) -> Option<CrawlConfig> {
+    let seed = params.options.uri.as_ref().map(|uri| normalize_uri(uri)).filter(|uri| uri.starts_with("http"))?;
+    Some(CrawlConfig {
+      base_uri: extract_base_uri(&seed),
+      seed,
+      mode: params.mode.unwrap_or_default(),
+      max_depth: params.depth.unwrap_or(DEFAULT_CRAWL_DEPTH).min(MAX_CRAWL_DEPTH),
+      max_pages: params.max_pages.unwrap_or(DEFAULT_CRAWL_PAGES).clamp(1, MAX_CRAWL_PAGES),
+      concurrency: params.concurrency.unwrap_or(DEFAULT_CONCURRENCY).clamp(1, MAX_CONCURRENCY),
+      same_domain: params.same_domain.unwrap_or(true),
+      // subdomains such as blog. or shop. are only crawled when asked for
+      subdomains: params.options.subdomains.unwrap_or(false),
+      skip_cache: params.options.skip.unwrap_or(false),
+      include: compile_patterns(&params.include_uris),
+      exclude: compile_patterns(&params.exclude_uris),
+      target: params.options.target.clone(),
+      show_links: params.options.elements.unwrap_or(true),
+      targets: params.options.targets.clone().unwrap_or_default(),
+      items: params.options.items.clone().unwrap_or_default(),
+      options: ContentOptions::from_params(&params.options),
+    })
+  }
+
+  // The seed is always crawled, other links must be within scope and match the patterns
+  pub fn in_scope(&self, uri: &str) -> bool {
+    (!self.same_domain || is_local_uri(uri, &self.base_uri, self.subdomains))
+      && (self.include.is_empty() || self.include.iter().any(|re| re.is_match(uri)))
+      && !self.exclude.iter().any(|re| re.is_match(uri))
+  }
+}
+
+// Breadth-first queue of crawl targets. Links found during a round are only handed out
+// after every target left over from shallower depths
+pub struct CrawlFrontier {
+  seen: HashSet<String>,
+  queue: Vec<CrawlTarget>,
+  next_level: Vec<CrawlTarget>,
+}
+
+impl CrawlFrontier {
+  pub fn new(seed: &str) -> Self {
+    CrawlFrontier {
+      seen: HashSet::from([seed.to_owned()]),
+      queue: vec![CrawlTarget { uri: seed.to_owned(), depth: 0, parent: None }],
+      next_level: vec![],
+    }
+  }
+
+  // Up to `budget` targets for the next round
+  pub fn next_batch(&mut self, budget: usize) -> Vec<CrawlTarget> {
+    self.queue.drain(..self.queue.len().min(budget)).collect()
+  }
+
+  // Queue unseen in-scope links of a crawled page unless it is at the depth limit
+  pub fn add_links(&mut self, page: &CrawlPage, links: Vec<String>, config: &CrawlConfig) {
+    if page.depth >= config.max_depth {
+      return;
+    }
+    for link in links {
+      if config.in_scope(&link) && self.seen.insert(link.clone()) {
+        self.next_level.push(CrawlTarget { uri: link, depth: page.depth + 1, parent: Some(page.uri.clone()) });
+      }
+    }
+  }
+
+  pub fn end_round(&mut self) {
+    self.queue.append(&mut self.next_level);
+  }
+
+  pub fn len(&self) -> usize {
+    self.queue.len() + self.next_level.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+// Extract the page with the configured options and collect its crawlable links.
+// Kept synchronous because scraper's Html is not Send, so it cannot be held across the crawl's awaits
+pub fn extract_crawl_page(target: &CrawlTarget, pd: &FlatPage, config: &CrawlConfig) -> (CrawlPage, Vec<String>) {
+  // PDFs and structured responses have no links to follow
+  let links = if is_html_page(pd) {
+    let html_obj = Html::parse_document(&clean_raw_html(&pd.content));
+    extract_links(&html_obj, &target.uri, config.subdomains, None).into_iter()
+      // unknown extensions, as in /users/john.doe or /2024.10.19, are more likely pages than files
+      .filter(|link| matches!(link.file_type.as_deref(), Some("html") | None))
+      .map(|link| link.uri)
+      .collect::<Vec<String>>()
+  } else {
+    vec![]
+  };
+  let data = match config.mode {
+    CrawlMode::Page => {
+      let show_mode = ShowMode::new(false, config.show_links);
+      CrawlExtract::Page(Box::new(build_content_data(&target.uri, pd, show_mode, true, config.target.clone(), false, &config.options)))
+    },
+    CrawlMode::Items => CrawlExtract::Items(Box::new(build_content_items(&target.uri, pd, &config.targets, &config.items))),
+  };
+  let page = CrawlPage {
+    uri: target.uri.clone(),
+    depth: target.depth,
+    parent: target.parent.clone(),
+    data: Some(data),
+    num_links: links.len(),
+    valid: true,
+  };
+  (page, links)
+}
+
+pub async fn crawl_page(target: CrawlTarget, config: &CrawlConfig) -> (CrawlPage, Vec<String>) {
+  match fetch_page(&target.uri, config.skip_cache).await {
+    Some(pd) => extract_crawl_page(&target, &pd, config),
+    None => (CrawlPage { uri: target.uri, depth: target.depth, parent: target.parent, data: None, num_links: 0, valid: false }, vec![])
+  }
+}
+
+pub async fn crawl_site(config: &CrawlConfig) -> CrawlResultSet {
+  crawl_site_with(config, |_, _| async { true }).await
+}
+
+// Breadth-first crawl from the seed, one depth level at a time with up to `concurrency` pages in flight.
+// `on_page` receives each page with the progress so far and may return false to stop the crawl
+pub async fn crawl_site_with<F, Fut>(config: &CrawlConfig, mut on_page: F) -> CrawlResultSet
+where F: FnMut(&CrawlPage, CrawlProgress) -> Fut, Fut: Future<Output = bool> {
+  let mut frontier = CrawlFrontier::new(&config.seed);
+  let mut pages: Vec<CrawlPage> = vec![];
+  let mut stopped = false;
+  while !stopped && !frontier.is_empty() && pages.len() < config.max_pages {
+    let batch = frontier.next_batch(config.max_pages - pages.len());
+    let mut in_flight = batch.len();
+    let mut results = stream::iter(batch)
+      .map(|target| crawl_page(target, config))
+      .buffer_unordered(config.concurrency);
+    // handle pages as they finish so callers can report them straight away
+    while let Some((page, links)) = results.next().await {
+      in_flight -= 1;
+      frontier.add_links(&page, links, config);
+      let queued = in_flight + frontier.len();
+      let progress = CrawlProgress { visited: pages.len() + 1, total: (pages.len() + 1 + queued).min(config.max_pages) };
+      stopped = !on_page(&page, progress).await;
+      pages.push(page);
+      if stopped {
+        break;
+      }
+    }
+    // unvisited targets at this depth go before the next level
+    frontier.end_round();
+  }
+  let valid = pages.iter().any(|page| page.valid);
+  CrawlResultSet {
+    seed: config.seed.clone(),
+    visited: pages.len(),
+    remaining: frontier.len(),
+    pages,
+    valid,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+  use crate::crawler::{CrawlConfig, CrawlFrontier, CrawlPage};
+  use crate::params::CrawlParams;
+
+  fn config() -> CrawlConfig {
+    let params: CrawlParams = serde_json::from_value(json!({
+      "uri": "https://example.com/#top", "depth": 2, "max_pages": 500, "concurrency": 99, "exclude_uris": ["/login"]
+    })).unwrap();
+    CrawlConfig::new(&params).unwrap()
+  }
+
+  fn crawled(uri: &str, depth: u8) -> CrawlPage {
+    CrawlPage { uri: uri.to_owned(), depth, parent: None, data: None, num_links: 0, valid: true }
+  }
+
+  fn to_links(links: &[&str]) -> Vec<String> {
+    links.iter().map(|l| l.to_string()).collect()
+  }
+
+  // a frontier whose seed has been crawled and has queued /a, /b and /c
+  fn seeded_frontier(config: &CrawlConfig) -> CrawlFrontier {
+    let mut frontier = CrawlFrontier::new(&config.seed);
+    let seed = frontier.next_batch(10);
+    frontier.add_links(&crawled(&seed[0].uri, 0), to_links(&["https://example.com/a", "https://example.com/b", "https://example.com/c"]), config);
+    frontier.end_round();
+    frontier
+  }
+
+  #[test]
+  fn test_config_normalizes_seed_and_clamps_limits() {
+    let config = config();
+    assert_eq!(config.seed, "https://example.com/");
+    assert_eq!(config.max_pages, 500);
+    assert_eq!(config.concurrency, 16);
+  }
+
+  #[test]
+  fn test_scope_stays_on_seed_host() {
+    let config = config();
+    assert!(!config.in_scope("https://blog.example.com/post"));
+    assert!(config.in_scope("https://www.example.com/post"));
+    assert!(!config.in_scope("https://other.org/"));
+  }
+
+  #[test]
+  fn test_scope_applies_exclude_patterns() {
+    assert!(!config().in_scope("https://example.com/login?next=/"));
+  }
+
+  #[test]
+  fn test_frontier_skips_seen_and_out_of_scope_links() {
+    let config = config();
+    let mut frontier = CrawlFrontier::new(&config.seed);
+    let seed = frontier.next_batch(10);
+    assert_eq!(seed.len(), 1);
+    let links = to_links(&["https://example.com/a", "https://example.com/b", "https://example.com/c", "https://other.org/x", "https://example.com/"]);
+    frontier.add_links(&crawled(&seed[0].uri, 0), links, &config);
+    frontier.end_round();
+    assert_eq!(frontier.len(), 3);
+  }
+
+  #[test]
+  fn test_page_budget_leaves_leftovers_before_deeper_links() {
+    let config = config();
+    let mut frontier = seeded_frontier(&config);
+    let round = frontier.next_batch(2);
+    assert_eq!(round.iter().map(|t| t.uri.as_str()).collect::<Vec<&str>>(), vec!["https://example.com/a", "https://example.com/b"]);
+    frontier.add_links(&crawled("https://example.com/a", 1), to_links(&["https://example.com/a/1", "https://example.com/b"]), &config);
+    frontier.end_round();
+    let round = frontier.next_batch(10);
+    assert_eq!(round.iter().map(|t| (t.uri.as_str(), t.depth)).collect::<Vec<_>>(), vec![("https://example.com/c", 1), ("https://example.com/a/1", 2)]);
+  }
+
+  #[test]
+  fn test_depth_limit_stops_expansion() {
+    let config = config();
+    let mut frontier = seeded_frontier(&config);
+    frontier.next_batch(10);
+    frontier.add_links(&crawled("https://example.com/a/1", 2), to_links(&["https://example.com/a/1/x"]), &config);
+    frontier.end_round();
+    assert!(frontier.is_empty());
+  }
+}
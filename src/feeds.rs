@@ -0,0 +1,267 @@
+use futures::stream::{self, StreamExt};
+use scraper::Html;
+use select::document::Document;
+use serde::Serialize;
+use serde_json::Value;
+use crate::byline::normalize_date;
+use crate::metadata::PageMetadata;
+use crate::page_data::{ContentOptions, ShowMode, fetch_page, fetch_page_data};
+use crate::params::{FeedParams, OutputFormat};
+use crate::uris::resolve_uri;
+use crate::cleantext::collapse_whitespace;
+
+const DEFAULT_FEED_ITEMS: usize = 50;
+// item pages fetched for their main text
+const MAX_TEXT_ITEMS: usize = 20;
+const TEXT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedItem {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub link: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub date: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub summary: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub author: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedResultSet {
+  pub uri: String,
+  // the discovered feed when the request URI was an HTML page
+  pub feed: String,
+  pub kind: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  pub items: Vec<FeedItem>,
+  pub valid: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParsedFeed {
+  pub kind: String,
+  pub title: Option<String>,
+  pub description: Option<String>,
+  pub items: Vec<FeedItem>,
+}
+
+// Feed summaries are often escaped HTML
+fn to_plain_text(html: &str) -> Option<String> {
+  let fragment = Html::parse_fragment(html);
+  let text = fragment.root_element().text().collect::<Vec<&str>>().join(" ");
+  Some(collapse_whitespace(&text)).filter(|t| !t.is_empty())
+}
+
+fn child<'a, 'input>(node: &roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+  node.children().find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn child_text(node: &roxmltree::Node, name: &str) -> Option<String> {
+  child(node, name).and_then(|c| c.text()).map(|t| t.trim().to_owned()).filter(|t| !t.is_empty())
+}
+
+fn first_text(node: &roxmltree::Node, names: &[&str]) -> Option<String> {
+  names.iter().find_map(|name| child_text(node, name))
+}
+
+// RSS 2.0 and RSS 1.0 (RDF) items
+fn parse_rss_item(node: &roxmltree::Node, base_uri: &str) -> FeedItem {
+  FeedItem {
+    title: child_text(node, "title").and_then(|t| to_plain_text(&t)),
+    link: child_text(node, "link").and_then(|l| resolve_uri(&l, base_uri)),
+    date: first_text(node, &["pubDate", "date", "published", "updated"]).and_then(|d| normalize_date(&d)),
+    summary: first_text(node, &["description", "summary", "encoded"]).and_then(|s| to_plain_text(&s)),
+    author: first_text(node, &["creator", "author"]),
+    id: child_text(node, "guid"),
+    text: None,
+  }
+}
+
+fn atom_link(node: &roxmltree::Node, base_uri: &str) -> Option<String> {
+  let links = node.children().filter(|c| c.is_element() && c.tag_name().name() == "link").collect::<Vec<roxmltree::Node>>();
+  links.iter().find(|l| l.attribute("rel").map(|rel| rel == "alternate").unwrap_or(true))
+    .or(links.first())
+    .and_then(|l| l.attribute("href"))
+    .and_then(|href| resolve_uri(href, base_uri))
+}
+
+fn parse_atom_entry(node: &roxmltree::Node, base_uri: &str) -> FeedItem {
+  FeedItem {
+    title: child_text(node, "title").and_then(|t| to_plain_text(&t)),
+    link: atom_link(node, base_uri),
+    date: first_text(node, &["published", "updated"]).and_then(|d| normalize_date(&d)),
+    summary: first_text(node, &["summary", "content"]).and_then(|s| to_plain_text(&s)),
+    author: child(node, "author").and_then(|a| child_text(&a, "name")),
+    id: child_text(node, "id"),
+    text: None,
+  }
+}
+
+fn parse_xml_feed(content: &str, base_uri: &str) -> Option<ParsedFeed> {
+  let doc = roxmltree::Document::parse(content.trim_start_matches('\u{feff}').trim()).ok()?;
+  let root = doc.root_element();
+  match root.tag_name().name() {
+    "feed" => Some(ParsedFeed {
+      kind: "atom".to_owned(),
+      title: child_text(&root, "title"),
+      description: child_text(&root, "subtitle"),
+      items: root.children().filter(|c| c.tag_name().name() == "entry").map(|entry| parse_atom_entry(&entry, base_uri)).collect(),
+    }),
+    "rss" | "RDF" => {
+      let channel = child(&root, "channel");
+      Some(ParsedFeed {
+        kind: "rss".to_owned(),
+        title: channel.and_then(|ch| child_text(&ch, "title")),
+        description: channel.and_then(|ch| child_text(&ch, "description")).and_then(|d| to_plain_text(&d)),
+        // RSS 1.0 items are siblings of the channel rather than children
+        items: root.descendants().filter(|c| c.is_element() && c.tag_name().name() == "item").map(|item| parse_rss_item(&item, base_uri)).collect(),
+      })
+    },
+    _ => None
+  }
+}
+
+fn json_str(value: &Value, keys: &[&str]) -> Option<String> {
+  keys.iter().find_map(|key| value.get(key).and_then(Value::as_str)).map(|s| s.trim().to_owned()).filter(|s| !s.is_empty())
+}
+
+fn parse_json_feed(content: &str, base_uri: &str) -> Option<ParsedFeed> {
+  let feed: Value = serde_json::from_str(content).ok()?;
+  if !json_str(&feed, &["version"]).map(|v| v.contains("jsonfeed")).unwrap_or(false) {
+    return None;
+  }
+  let items = feed.get("items").and_then(Value::as_array).cloned().unwrap_or_default().iter().map(|item| {
+    // version 1.1 uses an authors array in place of author
+    let author = item.get("authors").and_then(|a| a.get(0)).or(item.get("author")).and_then(|a| json_str(a, &["name"]));
+    FeedItem {
+      title: json_str(item, &["title"]),
+      link: json_str(item, &["url", "external_url"]).and_then(|u| resolve_uri(&u, base_uri)),
+      date: json_str(item, &["date_published", "date_modified"]).and_then(|d| normalize_date(&d)),
+      summary: json_str(item, &["summary", "content_text"]).or(json_str(item, &["content_html"]).and_then(|h| to_plain_text(&h))),
+      author,
+      id: json_str(item, &["id"]),
+      text: None,
+    }
+  }).collect();
+  Some(ParsedFeed {
+    kind: "json".to_owned(),
+    title: json_str(&feed, &["title"]),
+    description: json_str(&feed, &["description"]),
+    items,
+  })
+}
+
+pub fn parse_feed(content: &str, base_uri: &str) -> Option<ParsedFeed> {
+  parse_xml_feed(content, base_uri).or_else(|| parse_json_feed(content, base_uri))
+}
+
+// Feed URIs advertised by the alternate links of an HTML page
+fn discover_feeds(html: &str, uri: &str) -> Vec<String> {
+  let doc = Document::from(html);
+  PageMetadata::new(&doc, uri).feeds.into_iter().map(|fl| fl.uri).collect()
+}
+
+async fn item_text(link: Option<String>, skip_cache: bool) -> Option<String> {
+  let options = ContentOptions { format: OutputFormat::Text, pages: 1, ..Default::default() };
+  let result = fetch_page_data(&link?, ShowMode::new(false, false), true, None, false, skip_cache, &options).await;
+  result.best_text().filter(|t| !t.is_empty())
+}
+
+// Parse the URI as a feed, or else follow the first feed the page links to
+pub async fn fetch_feed(params: &FeedParams) -> Option<FeedResultSet> {
+  let uri = params.uri.clone()?;
+  let skip_cache = params.skip.unwrap_or(false);
+  let pd = fetch_page(&uri, skip_cache).await?;
+  let (feed_uri, parsed) = match parse_feed(&pd.content, &uri) {
+    Some(parsed) => (uri.clone(), parsed),
+    None => {
+      let mut found = None;
+      for feed_uri in discover_feeds(&pd.content, &uri) {
+        if let Some(parsed) = fetch_page(&feed_uri, skip_cache).await.and_then(|fd| parse_feed(&fd.content, &feed_uri)) {
+          found = Some((feed_uri, parsed));
+          break;
+        }
+      }
+      found?
+    }
+  };
+  let mut items = parsed.items;
+  items.truncate(params.limit.unwrap_or(DEFAULT_FEED_ITEMS));
+  if params.text.unwrap_or(false) {
+    let texts = stream::iter(items.iter().take(MAX_TEXT_ITEMS).map(|item| item.link.clone()).collect::<Vec<Option<String>>>())
+      .map(|link| item_text(link, skip_cache))
+      .buffered(TEXT_CONCURRENCY)
+      .collect::<Vec<Option<String>>>()
+      .await;
+    for (item, text) in items.iter_mut().zip(texts) {
+      item.text = text;
+    }
+  }
+  Some(FeedResultSet {
+    uri,
+    feed: feed_uri,
+    kind: parsed.kind,
+    title: parsed.title,
+    description: parsed.description,
+    valid: !items.is_empty(),
+    items,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::feeds::parse_feed;
+
+  #[test]
+  fn test_parse_rss() {
+    let rss = r#"<?xml version="1.0"?><rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/"><channel>
+      <title>Example News</title>
+      <item><title>First &amp; best</title><link>/news/1</link><pubDate>Tue, 05 Mar 2024 10:00:00 GMT</pubDate>
+        <description>&lt;p&gt;Short &lt;b&gt;summary&lt;/b&gt;&lt;/p&gt;</description><dc:creator>Jane Doe</dc:creator></item>
+      </channel></rss>"#;
+    let feed = parse_feed(rss, "https://example.com/feed").unwrap();
+    assert_eq!(feed.kind, "rss");
+    assert_eq!(feed.title.as_deref(), Some("Example News"));
+    let item = &feed.items[0];
+    assert_eq!(item.title.as_deref(), Some("First & best"));
+    assert_eq!(item.link.as_deref(), Some("https://example.com/news/1"));
+    assert_eq!(item.date.as_deref(), Some("2024-03-05T10:00:00+00:00"));
+    assert_eq!(item.summary.as_deref(), Some("Short summary"));
+    assert_eq!(item.author.as_deref(), Some("Jane Doe"));
+  }
+
+  #[test]
+  fn test_parse_atom() {
+    let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title>
+      <entry><title>Post</title><link rel="alternate" href="https://example.com/post"/><updated>2024-03-05T10:00:00Z</updated>
+        <author><name>Sam</name></author><summary>Hello</summary></entry></feed>"#;
+    let feed = parse_feed(atom, "https://example.com/atom.xml").unwrap();
+    assert_eq!(feed.kind, "atom");
+    assert_eq!(feed.items[0].link.as_deref(), Some("https://example.com/post"));
+    assert_eq!(feed.items[0].author.as_deref(), Some("Sam"));
+  }
+
+  #[test]
+  fn test_parse_json_feed() {
+    let json = r#"{"version": "https://jsonfeed.org/version/1.1", "title": "JF", "items": [{"id": "1", "url": "https://example.com/a", "content_html": "<p>Hi there</p>", "authors": [{"name": "Kim"}]}]}"#;
+    let feed = parse_feed(json, "https://example.com/feed.json").unwrap();
+    assert_eq!(feed.kind, "json");
+    assert_eq!(feed.items[0].summary.as_deref(), Some("Hi there"));
+    assert_eq!(feed.items[0].author.as_deref(), Some("Kim"));
+  }
+
+  #[test]
+  fn test_html_is_not_a_feed() {
+    assert!(parse_feed("<html><body>Not a feed</body></html>", "https://example.com").is_none());
+  }
+}
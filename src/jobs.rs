@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
+use redis::{aio, AsyncCommands};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use tokio::task::AbortHandle;
+use crate::cache::{get_timestamp, redis_async_client};
+use crate::batch::{BatchConfig, run_batch};
+use crate::crawler::{CrawlConfig, crawl_site_with};
+use crate::params::{BatchParams, CrawlParams, FeedParams, JobKind, PostParams, SitemapParams};
+use crate::routes::{endpoint_value, feed_value, sitemap_value};
+
+const ACTIVE_JOBS_KEY: &str = "jobs:active";
+const JOB_COUNTER_KEY: &str = "jobs:counter";
+// finished jobs and their results are kept for a week unless JOB_TTL is set
+const DEFAULT_JOB_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+  Queued,
+  Running,
+  Completed,
+  Failed,
+  Cancelled,
+}
+
+impl JobStatus {
+  pub fn is_finished(&self) -> bool {
+    matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default)]
+pub struct JobProgress {
+  pub done: usize,
+  pub total: usize,
+}
+
+// Crawl pages and batch items are appended to the job's result list as they finish,
+// other endpoints store their whole response as the result
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Job {
+  pub id: String,
+  pub kind: JobKind,
+  pub status: JobStatus,
+  pub params: Value,
+  pub progress: JobProgress,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub result: Option<Value>,
+  #[serde(rename = "numResults", default)]
+  pub num_results: usize,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub error: Option<String>,
+  pub created: i64,
+  pub updated: i64,
+}
+
+impl Job {
+  pub fn new(id: &str, kind: JobKind, params: Value) -> Self {
+    let ts = get_timestamp();
+    Job {
+      id: id.to_owned(),
+      kind,
+      status: JobStatus::Queued,
+      params,
+      progress: JobProgress { done: 0, total: 1 },
+      result: None,
+      num_results: 0,
+      error: None,
+      created: ts,
+      updated: ts,
+    }
+  }
+}
+
+fn job_key(id: &str) -> String {
+  format!("job:{}", id)
+}
+
+fn results_key(id: &str) -> String {
+  format!("job:{}:results", id)
+}
+
+fn job_ttl_secs() -> i64 {
+  dotenv::var("JOB_TTL").ok().and_then(|ttl| ttl.parse::<i64>().ok()).unwrap_or(DEFAULT_JOB_TTL_SECS)
+}
+
+// In-process handles so cancelled jobs stop immediately rather than at their next progress update
+fn running_jobs() -> &'static Mutex<HashMap<String, AbortHandle>> {
+  static RUNNING: OnceLock<Mutex<HashMap<String, AbortHandle>>> = OnceLock::new();
+  RUNNING.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+type JobConnection = Arc<tokio::sync::Mutex<aio::Connection>>;
+
+async fn save_job(connection: &mut aio::Connection, job: &Job) -> bool {
+  match serde_json::to_string(job) {
+    Ok(value) => connection.set::<String, String, ()>(job_key(&job.id), value).await.is_ok(),
+    Err(_error) => false,
+  }
+}
+
+async fn load_job(connection: &mut aio::Connection, id: &str) -> Option<Job> {
+  let value: String = connection.get(job_key(id)).await.ok()?;
+  serde_json::from_str(&value).ok()
+}
+
+pub async fn get_job(id: &str) -> Option<Job> {
+  let mut connection = redis_async_client().await.ok()?;
+  load_job(&mut connection, id).await
+}
+
+// Apply a change to a stored job unless it has already finished or been cancelled.
+// The job key is watched, so a change racing with a cancellation is retried and then skipped
+async fn update_job<F: FnMut(&mut Job)>(connection: &mut aio::Connection, id: &str, mut change: F) -> Option<Job> {
+  let key = job_key(id);
+  loop {
+    redis::cmd("WATCH").arg(&key).query_async::<_, ()>(connection).await.ok()?;
+    let job = load_job(connection, id).await.filter(|job| !job.status.is_finished());
+    let Some(mut job) = job else {
+      let _ = redis::cmd("UNWATCH").query_async::<_, ()>(connection).await;
+      return None;
+    };
+    change(&mut job);
+    job.updated = get_timestamp();
+    let value = serde_json::to_string(&job).ok()?;
+    // EXEC yields nil when the key changed after WATCH
+    let saved: Option<(String,)> = redis::pipe().atomic().set(&key, value).query_async(connection).await.ok()?;
+    if saved.is_some() {
+      return Some(job);
+    }
+  }
+}
+
+// The length of the result list, or None if the item could not be stored
+async fn push_result(connection: &mut aio::Connection, id: &str, item: &Value) -> Option<usize> {
+  connection.rpush::<String, String, usize>(results_key(id), item.to_string()).await.ok()
+}
+
+async fn load_results(connection: &mut aio::Connection, id: &str, offset: usize, limit: usize) -> Vec<Value> {
+  if limit < 1 {
+    return vec![];
+  }
+  let end = offset.saturating_add(limit - 1).min(isize::MAX as usize) as isize;
+  let items: Vec<String> = connection.lrange(results_key(id), offset as isize, end).await.unwrap_or_default();
+  items.iter().filter_map(|item| serde_json::from_str(item).ok()).collect()
+}
+
+pub async fn get_job_results(id: &str, offset: usize, limit: usize) -> Vec<Value> {
+  match redis_async_client().await {
+    Ok(mut connection) => load_results(&mut connection, id, offset, limit).await,
+    Err(_error) => vec![]
+  }
+}
+
+// Drop the job from the resume list and let it expire
+async fn finish_job(id: &str) {
+  if let Ok(mut connection) = redis_async_client().await {
+    let ttl = job_ttl_secs() as usize;
+    let _ = connection.srem::<&str, &str, usize>(ACTIVE_JOBS_KEY, id).await;
+    let _ = connection.expire::<String, usize>(job_key(id), ttl).await;
+    let _ = connection.expire::<String, usize>(results_key(id), ttl).await;
+  }
+  running_jobs().lock().unwrap_or_else(|e| e.into_inner()).remove(id);
+}
+
+fn parse_params<T: DeserializeOwned>(params: &Value) -> Result<T, String> {
+  serde_json::from_value(params.clone()).map_err(|error| error.to_string())
+}
+
+// Check the request body matches the endpoint before queuing
+pub fn validate_job_params(kind: JobKind, params: &Value) -> Result<(), String> {
+  let uri = match kind {
+    JobKind::Crawl => parse_params::<CrawlParams>(params)?.options.uri,
+    JobKind::Batch => return BatchConfig::new(&parse_params::<BatchParams>(params)?).map(|_| ()),
+    JobKind::GetSitemap => parse_params::<SitemapParams>(params)?.uri,
+    JobKind::GetFeed => parse_params::<FeedParams>(params)?.uri,
+    _ => parse_params::<PostParams>(params)?.uri,
+  };
+  match uri {
+    Some(_) => Ok(()),
+    None => Err("missing uri".to_owned())
+  }
+}
+
+// Store a new job and start it in the background
+pub async fn create_job(kind: JobKind, params: Value) -> Result<Job, String> {
+  validate_job_params(kind, &params)?;
+  let mut connection = redis_async_client().await.map_err(|error| error.to_string())?;
+  let counter: i64 = connection.incr(JOB_COUNTER_KEY, 1).await.map_err(|error| error.to_string())?;
+  let job = Job::new(&format!("{:x}-{:x}", get_timestamp(), counter), kind, params);
+  if !save_job(&mut connection, &job).await {
+    return Err("job could not be stored".to_owned());
+  }
+  let _ = connection.sadd::<&str, &str, usize>(ACTIVE_JOBS_KEY, &job.id).await;
+  spawn_job(job.clone());
+  Ok(job)
+}
+
+pub async fn cancel_job(id: &str) -> Option<Job> {
+  let mut connection = redis_async_client().await.ok()?;
+  let job = update_job(&mut connection, id, |job| job.status = JobStatus::Cancelled).await?;
+  if let Some(handle) = running_jobs().lock().unwrap_or_else(|e| e.into_inner()).get(id) {
+    handle.abort();
+  }
+  finish_job(id).await;
+  Some(job)
+}
+
+fn spawn_job(job: Job) {
+  let id = job.id.clone();
+  let handle = tokio::spawn(run_job(job));
+  let mut running = running_jobs().lock().unwrap_or_else(|e| e.into_inner());
+  running.retain(|_, handle| !handle.is_finished());
+  running.insert(id, handle.abort_handle());
+}
+
+// Restart jobs interrupted by a shutdown. Results stored before the restart are kept,
+// and run_job skips the pages or items they already cover
+pub async fn resume_jobs() -> usize {
+  let Ok(mut connection) = redis_async_client().await else {
+    return 0;
+  };
+  let ids: Vec<String> = connection.smembers(ACTIVE_JOBS_KEY).await.unwrap_or_default();
+  let mut resumed: usize = 0;
+  for id in ids {
+    match load_job(&mut connection, &id).await.filter(|job| !job.status.is_finished()) {
+      Some(mut job) => {
+        job.status = JobStatus::Queued;
+        job.updated = get_timestamp();
+        if save_job(&mut connection, &job).await {
+          spawn_job(job);
+          resumed += 1;
+        }
+      },
+      None => {
+        let _ = connection.srem::<&str, &str, usize>(ACTIVE_JOBS_KEY, &id).await;
+      }
+    }
+  }
+  resumed
+}
+
+// Results already stored by an earlier run of the job
+async fn stored_results(connection: &JobConnection, id: &str) -> Vec<Value> {
+  load_results(&mut *connection.lock().await, id, 0, usize::MAX).await
+}
+
+// Append one page or item and record the progress. The connection is locked for the whole update
+// so the job's writes stay in order
+async fn record_result(connection: &JobConnection, id: &str, item: Option<Value>, progress: JobProgress) -> bool {
+  let mut connection = connection.lock().await;
+  let num_results = match item {
+    Some(item) => match push_result(&mut connection, id, &item).await {
+      Some(len) => Some(len),
+      None => return false,
+    },
+    None => None,
+  };
+  update_job(&mut connection, id, |job| {
+    job.progress = progress;
+    if let Some(len) = num_results {
+      job.num_results = len;
+    }
+  }).await.is_some()
+}
+
+// Pages stored before a restart are crawled again for their links but not stored twice
+async fn run_crawl(connection: &JobConnection, id: &str, params: &CrawlParams) -> Result<Value, String> {
+  let config = CrawlConfig::new(params).ok_or("invalid seed uri".to_owned())?;
+  let stored: HashSet<String> = stored_results(connection, id).await.iter()
+    .filter_map(|page| page.get("uri").and_then(Value::as_str).map(|uri| uri.to_owned()))
+    .collect();
+  let result = crawl_site_with(&config, |page, progress| {
+    let item = Some(json!(page)).filter(|_| !stored.contains(&page.uri));
+    let progress = JobProgress { done: progress.visited, total: progress.total };
+    record_result(connection, id, item, progress)
+  }).await;
+  // the pages are already in the result list
+  Ok(result.summary())
+}
+
+// Items stored before a restart are identified by their index and not fetched again
+async fn run_batch_job(connection: &JobConnection, id: &str, params: &BatchParams) -> Result<Value, String> {
+  let mut config = BatchConfig::new(params)?;
+  config.completed = stored_results(connection, id).await.iter()
+    .filter_map(|item| item.get("index").and_then(Value::as_u64).map(|index| index as usize))
+    .collect();
+  let result = run_batch(&config, |item, progress| {
+    let progress = JobProgress { done: progress.done, total: progress.total };
+    record_result(connection, id, Some(json!(item)), progress)
+  }).await;
+  // the items are already in the result list
+  Ok(result.summary())
+}
+
+async fn run_kind(connection: &JobConnection, id: &str, kind: JobKind, params: &Value) -> Result<Value, String> {
+  let result = match kind {
+    JobKind::Crawl => run_crawl(connection, id, &parse_params(params)?).await?,
+    JobKind::Batch => run_batch_job(connection, id, &parse_params(params)?).await?,
+    JobKind::GetSitemap => sitemap_value(&parse_params(params)?).await,
+    JobKind::GetFeed => feed_value(&parse_params(params)?).await,
+    _ => endpoint_value(kind, &parse_params(params)?).await.ok_or("unsupported job kind".to_owned())?,
+  };
+  // a job whose only result is invalid has failed, with the endpoint's own error if it gave one
+  if result.get("valid").and_then(Value::as_bool) == Some(false) {
+    let error = result.get("error").and_then(Value::as_str).unwrap_or("no valid result");
+    return Err(error.to_owned());
+  }
+  Ok(result)
+}
+
+async fn run_job(job: Job) {
+  let id = job.id.clone();
+  let Ok(mut connection) = redis_async_client().await else {
+    finish_job(&id).await;
+    return;
+  };
+  if update_job(&mut connection, &id, |job| job.status = JobStatus::Running).await.is_some() {
+    let connection: JobConnection = Arc::new(tokio::sync::Mutex::new(connection));
+    let outcome = run_kind(&connection, &id, job.kind, &job.params).await;
+    update_job(&mut *connection.lock().await, &id, |job| {
+      match &outcome {
+        Ok(result) => {
+          job.status = JobStatus::Completed;
+          job.result = Some(result.clone());
+          job.progress.done = job.progress.total;
+        },
+        Err(error) => {
+          job.status = JobStatus::Failed;
+          job.error = Some(error.clone());
+        }
+      }
+    }).await;
+  }
+  finish_job(&id).await;
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+  use crate::jobs::validate_job_params;
+  use crate::params::JobKind;
+
+  #[test]
+  fn test_valid_job_params() {
+    assert!(validate_job_params(JobKind::GetPage, &json!({ "uri": "https://example.com", "pages": 3 })).is_ok());
+    assert!(validate_job_params(JobKind::Crawl, &json!({ "uri": "https://example.com", "depth": 2, "mode": "items" })).is_ok());
+  }
+
+  #[test]
+  fn test_missing_uri_is_rejected() {
+    assert!(validate_job_params(JobKind::GetLinks, &json!({ "full": true })).is_err());
+  }
+
+  #[test]
+  fn test_unknown_crawl_mode_is_rejected() {
+    assert!(validate_job_params(JobKind::Crawl, &json!({ "uri": "https://example.com", "mode": "site" })).is_err());
+  }
+}
@@ -0,0 +1,133 @@
+use select::document::Document;
+use select::predicate::Name;
+use serde::Serialize;
+use crate::stats::{extract_lang_from_doc, extract_meta_from_doc};
+
+// enough text for a stable trigram profile without scanning very long pages
+const MAX_DETECTION_CHARS: usize = 4000;
+const MIN_DETECTION_CHARS: usize = 24;
+// ISO 639-3 codes reported by the detector mapped to the ISO 639-1 codes used in html lang attributes
+const ISO_639_1_CODES: [(&str, &str); 68] = [
+  ("afr", "af"), ("aka", "ak"), ("amh", "am"), ("ara", "ar"), ("aze", "az"), ("bel", "be"), ("ben", "bn"), ("bul", "bg"),
+  ("cat", "ca"), ("ces", "cs"), ("cmn", "zh"), ("dan", "da"), ("deu", "de"), ("ell", "el"), ("eng", "en"), ("epo", "eo"),
+  ("est", "et"), ("fin", "fi"), ("fra", "fr"), ("guj", "gu"), ("heb", "he"), ("hin", "hi"), ("hrv", "hr"), ("hun", "hu"),
+  ("hye", "hy"), ("ind", "id"), ("ita", "it"), ("jav", "jv"), ("jpn", "ja"), ("kan", "kn"), ("kat", "ka"), ("khm", "km"),
+  ("kor", "ko"), ("lat", "la"), ("lav", "lv"), ("lit", "lt"), ("mal", "ml"), ("mar", "mr"), ("mkd", "mk"), ("mya", "my"),
+  ("nep", "ne"), ("nld", "nl"), ("nob", "nb"), ("ori", "or"), ("pan", "pa"), ("pes", "fa"), ("pol", "pl"), ("por", "pt"),
+  ("ron", "ro"), ("rus", "ru"), ("sin", "si"), ("slk", "sk"), ("slv", "sl"), ("sna", "sn"), ("spa", "es"), ("srp", "sr"),
+  ("swe", "sv"), ("tam", "ta"), ("tel", "te"), ("tgl", "tl"), ("tha", "th"), ("tuk", "tk"), ("tur", "tr"), ("ukr", "uk"),
+  ("urd", "ur"), ("uzb", "uz"), ("vie", "vi"), ("yid", "yi"),
+];
+// declared codes that are equivalent to a detected code
+const LANG_ALIASES: [(&str, &str); 5] = [("no", "nb"), ("nn", "nb"), ("iw", "he"), ("in", "id"), ("fil", "tl")];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageInfo {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub declared: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub detected: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+  pub confidence: f64,
+  pub reliable: bool,
+  pub mismatch: bool,
+}
+
+impl LanguageInfo {
+  pub fn new(declared: Option<String>, text: &str) -> LanguageInfo {
+    let sample: String = text.chars().take(MAX_DETECTION_CHARS).collect();
+    let info = if sample.trim().chars().count() >= MIN_DETECTION_CHARS {
+      whatlang::detect(&sample)
+    } else {
+      None
+    };
+    let detected = info.as_ref().map(|inf| to_iso_639_1(inf.lang().code()));
+    let reliable = info.as_ref().map(|inf| inf.is_reliable()).unwrap_or(false);
+    let mismatch = match (&declared, &detected) {
+      (Some(dec), Some(det)) => reliable && primary_subtag(dec) != *det,
+      _ => false
+    };
+    LanguageInfo {
+      declared,
+      detected,
+      name: info.as_ref().map(|inf| inf.lang().eng_name().to_string()),
+      confidence: info.map(|inf| (inf.confidence() * 1000.0).round() / 1000.0).unwrap_or(0.0),
+      reliable,
+      mismatch,
+    }
+  }
+}
+
+fn to_iso_639_1(code: &str) -> String {
+  ISO_639_1_CODES.iter().find(|(long, _)| *long == code).map(|(_, short)| short.to_string()).unwrap_or(code.to_string())
+}
+
+// en-GB => en, pt_BR => pt, plus legacy aliases
+pub fn primary_subtag(lang: &str) -> String {
+  let primary = lang.trim().split(['-', '_']).next().unwrap_or("").to_lowercase();
+  LANG_ALIASES.iter().find(|(alias, _)| *alias == primary).map(|(_, code)| code.to_string()).unwrap_or(primary)
+}
+
+// http-equiv values are case-insensitive and usually written as Content-Language
+fn extract_content_language(doc: &Document) -> Option<String> {
+  doc.find(Name("meta"))
+    .find(|node| node.attr("http-equiv").map(|he| he.eq_ignore_ascii_case("content-language")).unwrap_or(false))
+    .and_then(|node| node.attr("content").map(|c| c.to_owned()))
+}
+
+// The html lang attribute, falling back to content-language and og:locale declarations
+pub fn extract_declared_lang(doc: &Document) -> Option<String> {
+  extract_lang_from_doc(doc)
+    .or_else(|| extract_content_language(doc))
+    .or_else(|| extract_meta_from_doc(doc, "property", "og:locale"))
+    .map(|lang| lang.trim().to_string())
+    .filter(|lang| !lang.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use select::document::Document;
+  use crate::language::{LanguageInfo, extract_declared_lang, primary_subtag};
+
+  #[test]
+  fn test_primary_subtag() {
+    assert_eq!(primary_subtag(" en-GB "), "en");
+    assert_eq!(primary_subtag("pt_BR"), "pt");
+    assert_eq!(primary_subtag("no"), "nb");
+  }
+
+  #[test]
+  fn test_detection_matches_declared() {
+    let english = "The committee met on Tuesday to discuss the new budget for schools and hospitals across the region.";
+    let info = LanguageInfo::new(Some("en-US".to_string()), english);
+    assert_eq!(info.detected.as_deref(), Some("en"));
+    assert_eq!(info.name.as_deref(), Some("English"));
+    assert!(!info.mismatch);
+  }
+
+  #[test]
+  fn test_detection_flags_mismatch() {
+    let german = "Der Ausschuss traf sich am Dienstag, um den neuen Haushalt für Schulen und Krankenhäuser in der Region zu besprechen.";
+    let info = LanguageInfo::new(Some("en".to_string()), german);
+    assert_eq!(info.detected.as_deref(), Some("de"));
+    assert!(info.reliable && info.mismatch);
+  }
+
+  #[test]
+  fn test_short_text_is_not_detected() {
+    let info = LanguageInfo::new(Some("fr".to_string()), "Bonjour");
+    assert_eq!(info.detected, None);
+    assert!(!info.mismatch);
+  }
+
+  #[test]
+  fn test_declared_lang_precedence() {
+    let doc = Document::from(r#"<html lang="fr-CA"><head><meta property="og:locale" content="en_GB"></head></html>"#);
+    assert_eq!(extract_declared_lang(&doc).as_deref(), Some("fr-CA"));
+    let doc = Document::from(r#"<html><head><meta http-equiv="Content-Language" content=" de "><meta property="og:locale" content="en_GB"></head></html>"#);
+    assert_eq!(extract_declared_lang(&doc).as_deref(), Some("de"));
+    let doc = Document::from(r#"<html><head><meta property="og:locale" content="en_GB"></head></html>"#);
+    assert_eq!(extract_declared_lang(&doc).as_deref(), Some("en_GB"));
+  }
+}
@@ -0,0 +1,184 @@
+use scraper::{ElementRef, Html};
+use string_patterns::*;
+use crate::cleantext::{collapse_whitespace, strip_literal_tags};
+use crate::page_data::LinkItem;
+use crate::params::LinkFilter;
+use crate::regions::{RegionKind, region_hint};
+use crate::stats::{extract_base_uri, is_local_uri};
+use crate::uris::{html_base_uri, resolve_uri};
+
+const CONTEXT_TAGS: [&str; 12] = ["p", "li", "td", "th", "dd", "dt", "blockquote", "figcaption", "caption", "h2", "h3", "h4"];
+const HEADING_TAGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+const PAGE_EXTENSIONS: [&str; 8] = ["html", "htm", "xhtml", "php", "asp", "aspx", "jsp", "cfm"];
+const FILE_EXTENSIONS: [&str; 30] = [
+  "pdf", "doc", "docx", "odt", "rtf", "txt", "xls", "xlsx", "ods", "csv", "ppt", "pptx", "odp", "epub", "zip",
+  "gz", "tgz", "tar", "rar", "7z", "jpg", "jpeg", "png", "gif", "webp", "svg", "mp3", "mp4", "json", "xml",
+];
+const MAX_SUMMARY_LEN: usize = 320;
+
+fn element_text(elem: &ElementRef) -> String {
+  collapse_whitespace(&elem.text().collect::<String>())
+}
+
+pub fn is_javascript_link(title: &str, uri: &str) -> bool {
+    let patterns = [r"\{", r"\}"];
+    let title_suspect = title.to_owned().pattern_match_all(&patterns, true);
+    if !title_suspect {
+        uri.to_owned().pattern_match_all(&patterns, true)
+    } else {
+        title_suspect
+    }
+}
+
+// Extension of the URI path, or html for page-like paths without a known file extension
+pub fn guess_file_type(uri: &str) -> Option<String> {
+  let path = uri.split(['?', '#']).next().unwrap_or("");
+  let last_segment = path.split("://").last().unwrap_or("").rsplit('/').next().unwrap_or("");
+  match last_segment.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()) {
+    Some(ext) if FILE_EXTENSIONS.contains(&ext.as_str()) => Some(ext),
+    Some(ext) if PAGE_EXTENSIONS.contains(&ext.as_str()) => Some("html".to_string()),
+    Some(_) => None,
+    None => Some("html".to_string())
+  }
+}
+
+// The sentence around the link text within its nearest paragraph-like block
+fn link_context(elem: &ElementRef, title: &str) -> String {
+  let block = elem.ancestors().filter_map(ElementRef::wrap).find(|el| CONTEXT_TAGS.contains(&el.value().name()));
+  let text = block.map(|el| element_text(&el)).unwrap_or_default();
+  if text.len() <= title.len() {
+    return "".to_string();
+  }
+  let Some(pos) = text.find(title) else {
+    return text.chars().take(MAX_SUMMARY_LEN).collect();
+  };
+  let is_boundary = |c: char| matches!(c, '.' | '!' | '?' | '…');
+  // byte offsets just past the boundary character, as '…' spans three bytes
+  let start = text[..pos].char_indices().rev().find(|(_, c)| is_boundary(*c)).map(|(i, c)| i + c.len_utf8()).unwrap_or(0);
+  let after = pos + title.len();
+  let end = text[after..].char_indices().find(|(_, c)| is_boundary(*c)).map(|(i, c)| after + i + c.len_utf8()).unwrap_or(text.len());
+  let sentence = text[start..end].trim();
+  if sentence.chars().count() > MAX_SUMMARY_LEN {
+    sentence.chars().take(MAX_SUMMARY_LEN).collect::<String>() + "…"
+  } else {
+    sentence.to_string()
+  }
+}
+
+// The first region hinted by the link's ancestors
+fn link_region(elem: &ElementRef) -> Option<RegionKind> {
+  elem.ancestors().filter_map(ElementRef::wrap).find_map(|el| {
+    let classes = el.value().classes().map(|c| c.to_string()).collect::<Vec<String>>();
+    region_hint(el.value().name(), el.value().attr("role"), &classes, el.value().id())
+  })
+}
+
+fn link_title(elem: &ElementRef) -> String {
+  let title = strip_literal_tags(&element_text(elem));
+  if !title.is_empty() {
+    return title;
+  }
+  // icon and image links are labelled by attributes
+  ["aria-label", "title"].iter().find_map(|name| elem.value().attr(name))
+    .or_else(|| elem.descendants().filter_map(ElementRef::wrap).find_map(|el| el.value().attr("alt")))
+    .map(collapse_whitespace)
+    .unwrap_or_default()
+}
+
+fn matches_filter(link: &LinkItem, filter: &LinkFilter) -> bool {
+  filter.local.map(|local| link.local == local).unwrap_or(true)
+    && filter.regions.as_ref().map(|regions| link.region.map(|r| regions.contains(&r)).unwrap_or(false)).unwrap_or(true)
+    && filter.pattern.as_ref().map(|pat| link.uri.pattern_match(pat, true)).unwrap_or(true)
+    && filter.rel.as_ref().map(|rel| link.rel.iter().any(|r| r.eq_ignore_ascii_case(rel))).unwrap_or(true)
+}
+
+// All unique links with their sentence context, rel attributes, page region and nearest preceding heading
+pub fn extract_links(html_obj: &Html, uri: &str, include_subdomains: bool, filter: Option<&LinkFilter>) -> Vec<LinkItem> {
+  let base_uri = extract_base_uri(uri);
+  let link_base_uri = html_base_uri(html_obj, uri);
+  let mut links: Vec<LinkItem> = vec![];
+  let mut heading: Option<String> = None;
+  // walk in document order so the latest heading precedes each link
+  for elem in html_obj.root_element().descendants().filter_map(ElementRef::wrap) {
+    let tag = elem.value().name();
+    if HEADING_TAGS.contains(&tag) {
+      heading = Some(element_text(&elem)).filter(|h| !h.is_empty());
+      continue;
+    }
+    if tag != "a" {
+      continue;
+    }
+    let Some(href) = elem.value().attr("href") else {
+      continue;
+    };
+    let title = link_title(&elem);
+    if title.is_empty() || is_javascript_link(&title, href) || href.starts_with('#') {
+      continue;
+    }
+    let Some(full_uri) = resolve_uri(href, &link_base_uri) else {
+      continue;
+    };
+    if links.iter().any(|lk| lk.uri == full_uri) {
+      continue;
+    }
+    let local = is_local_uri(&full_uri, &base_uri, include_subdomains);
+    let mut link = LinkItem::new(&full_uri, &title, &link_context(&elem, &title), local);
+    link.rel = elem.value().attr("rel").map(|rel| rel.split_whitespace().map(|r| r.to_lowercase()).collect()).unwrap_or_default();
+    link.target = elem.value().attr("target").map(|t| t.to_string());
+    link.hreflang = elem.value().attr("hreflang").map(|h| h.to_string());
+    link.region = link_region(&elem);
+    link.heading = heading.clone().filter(|h| *h != title);
+    link.file_type = guess_file_type(&full_uri);
+    if filter.map(|flt| matches_filter(&link, flt)).unwrap_or(true) {
+      links.push(link);
+    }
+  }
+  links
+}
+
+#[cfg(test)]
+mod tests {
+  use scraper::Html;
+  use crate::links::extract_links;
+  use crate::params::LinkFilter;
+  use crate::regions::RegionKind;
+
+  fn report_page() -> Html {
+    Html::parse_fragment(r#"<nav><ul><li><a href="/">Home</a></li></ul></nav><main><h2>Reports</h2>
+      <p>Sales rose sharply. Read the <a href="/files/report.PDF?v=2" rel="nofollow" target="_blank">annual report</a> for details. More soon.</p>
+      <p><a href="https://partner.org/page" rel="sponsored">Partner</a></p></main>"#)
+  }
+
+  #[test]
+  fn test_link_regions() {
+    let links = extract_links(&report_page(), "https://example.com/news", true, None);
+    assert_eq!(links.len(), 3);
+    assert_eq!(links[0].region, Some(RegionKind::Navigation));
+    assert_eq!(links[1].region, Some(RegionKind::Main));
+  }
+
+  #[test]
+  fn test_link_context() {
+    let links = extract_links(&report_page(), "https://example.com/news", true, None);
+    let report = &links[1];
+    assert_eq!(report.summary, "Read the annual report for details.");
+    assert_eq!(report.rel, vec!["nofollow"]);
+    assert_eq!(report.file_type.as_deref(), Some("pdf"));
+    assert_eq!(report.heading.as_deref(), Some("Reports"));
+  }
+
+  #[test]
+  fn test_link_filter() {
+    let filter = LinkFilter { local: Some(false), ..Default::default() };
+    let external = extract_links(&report_page(), "https://example.com/news", true, Some(&filter));
+    assert_eq!(external.len(), 1);
+    assert_eq!(external[0].uri, "https://partner.org/page");
+  }
+
+  #[test]
+  fn test_summary_ellipsis_is_not_a_sentence_end() {
+    let ellipsis = Html::parse_fragment(r#"<p>Wait for it… then see <a href="/more">the sequel</a> soon… Or not.</p>"#);
+    let links = extract_links(&ellipsis, "https://example.com", true, None);
+    assert_eq!(links[0].summary, "then see the sequel soon…");
+  }
+}
@@ -9,6 +9,29 @@ mod cleantext;
 mod browsergrab;
 mod is_truthy;
 mod expand_path;
+mod plaintext;
+mod metadata;
+mod byline;
+mod language;
+mod metrics;
+mod outline;
+mod tables;
+mod media;
+mod navigation;
+mod regions;
+mod overlays;
+mod pagination;
+mod uris;
+mod links;
+mod crawler;
+mod batch;
+mod sitemap;
+mod feeds;
+mod pdf;
+mod xpath;
+mod structured;
+mod jobs;
+mod streaming;
 
 use axum::Router;
 use std::net::SocketAddr;
@@ -37,6 +60,11 @@ fn get_max_timeout_secs() -> u64 {
     }
 }
 
+// batches with hundreds of uris need more than the old 8KB request body limit
+fn get_max_body_size() -> usize {
+    dotenv::var("MAX_BODY_SIZE").ok().and_then(|size| size.parse::<usize>().ok()).unwrap_or(65536)
+}
+
 fn get_port_number() -> u16 {
     let env_port = if let Ok(port_ref) = dotenv::var("PORT") { port_ref } else { "3000".to_owned() };
     if let Ok(p) = u16::from_str_radix(&env_port, 10) {
@@ -53,21 +81,38 @@ async fn main() {
     let app = Router::new()
         // `GET /` goes to `root`
         .route("/page-stats", get(page_data_response).post(page_data_response_post))
+        .route("/page-stats/stream", post(page_data_stream_post))
         .route("/get-page", post(page_content_response_post))
         .route("/get-content-items", post(fetch_page_content_items))
         .route("/get-links", post(page_links_response_post))
+        .route("/get-tables", post(fetch_page_tables))
+        .route("/get-navigation", post(fetch_page_navigation))
+        .route("/get-sitemap", post(fetch_sitemap))
+        .route("/get-feed", post(fetch_feed_response))
+        .route("/crawl", post(crawl_site_response))
+        .route("/crawl/stream", post(crawl_site_stream))
+        .route("/batch", post(batch_response))
+        .route("/batch/stream", post(batch_stream))
+        .route("/jobs", post(create_job_response))
+        .route("/jobs/:id", get(job_status_response).delete(cancel_job_response))
+        .route("/jobs/:id/cancel", post(cancel_job_response))
         .route("/get-page-from-browser", post(fetch_page_content_from_browser))
         .route("/from-browser", post(fetch_page_from_browser))
         .layer(CorsLayer::permissive())
         .layer(TimeoutLayer::new(Duration::from_secs(max_timeout_secs)))
-        // don't allow request bodies larger than 1024 bytes, returning 413 status code
-        .layer(RequestBodyLimitLayer::new(8192))
+        // don't allow request bodies larger than MAX_BODY_SIZE bytes, returning 413 status code
+        .layer(RequestBodyLimitLayer::new(get_max_body_size()))
         .layer(TraceLayer::new_for_http())
         .layer(SetResponseHeaderLayer::if_not_present(
             header::SERVER,
             HeaderValue::from_static("rust-axum"),
         ));
     let app = app.fallback(handler_404);
+    // pick up jobs that were queued or running when the server last stopped
+    let resumed = jobs::resume_jobs().await;
+    if resumed > 0 {
+        tracing::debug!("resumed {} jobs", resumed);
+    }
     let port = get_port_number();
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     tracing::debug!("listening on {}", addr);
@@ -0,0 +1,230 @@
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use crate::uris::{html_base_uri, resolve_uri};
+use crate::cleantext::collapse_whitespace;
+
+pub const EMBEDDED_MEDIA_SELECTOR: &str = "img,video,audio,iframe,embed";
+// attributes used by lazy-loading scripts, in order of preference
+const LAZY_SRC_ATTRS: [&str; 5] = ["data-src", "data-lazy-src", "data-original", "data-lazy", "data-url"];
+const LAZY_SRCSET_ATTRS: [&str; 3] = ["data-srcset", "data-lazy-srcset", "srcset"];
+const MIME_TYPES: [(&str, &str); 16] = [
+  ("jpg", "image/jpeg"), ("jpeg", "image/jpeg"), ("png", "image/png"), ("gif", "image/gif"), ("webp", "image/webp"),
+  ("avif", "image/avif"), ("svg", "image/svg+xml"), ("bmp", "image/bmp"), ("mp4", "video/mp4"), ("webm", "video/webm"),
+  ("ogv", "video/ogg"), ("mov", "video/quicktime"), ("mp3", "audio/mpeg"), ("ogg", "audio/ogg"), ("wav", "audio/wav"),
+  ("m4a", "audio/mp4"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaItem {
+  pub kind: String,
+  pub src: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub alt: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub caption: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub width: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub height: Option<u32>,
+  #[serde(rename = "mimeType", skip_serializing_if = "Option::is_none")]
+  pub mime_type: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub poster: Option<String>,
+}
+
+fn non_empty_attr(elem: &ElementRef, name: &str) -> Option<String> {
+  elem.value().attr(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty() && !v.starts_with("data:"))
+}
+
+fn dimension_attr(elem: &ElementRef, name: &str) -> Option<u32> {
+  elem.value().attr(name).and_then(|v| v.trim().trim_end_matches("px").parse::<u32>().ok())
+}
+
+// URL and optional descriptor of each srcset candidate. URLs run to the next whitespace and may contain commas,
+// so a comma only ends a candidate at the end of its URL or after its descriptor
+fn parse_srcset(srcset: &str) -> Vec<(String, Option<String>)> {
+  let mut candidates: Vec<(String, Option<String>)> = vec![];
+  let mut rest = srcset;
+  loop {
+    rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    if rest.is_empty() {
+      break;
+    }
+    let url_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (raw_url, after_url) = rest.split_at(url_end);
+    let url = raw_url.trim_end_matches(',');
+    rest = after_url;
+    let descriptor = if url.len() < raw_url.len() {
+      None
+    } else {
+      let desc_end = rest.find(',').unwrap_or(rest.len());
+      let (desc, after_desc) = rest.split_at(desc_end);
+      rest = after_desc;
+      Some(desc.trim().to_string()).filter(|d| !d.is_empty())
+    };
+    if !url.is_empty() {
+      candidates.push((url.to_string(), descriptor));
+    }
+  }
+  candidates
+}
+
+// Pick the widest (or highest density) candidate from one or more srcset attributes
+pub fn best_srcset_candidate<'a>(srcsets: impl Iterator<Item = &'a str>) -> Option<String> {
+  let mut best: Option<(f64, String)> = None;
+  for (url, descriptor) in srcsets.flat_map(parse_srcset) {
+    let descriptor = descriptor.unwrap_or("1x".to_string());
+    let size = descriptor.trim_end_matches(['w', 'x']).parse::<f64>().unwrap_or(1.0);
+    // width descriptors outrank density descriptors
+    let weight = if descriptor.ends_with('w') { size } else { size / 1000.0 };
+    if !url.starts_with("data:") && best.as_ref().map(|(w, _)| weight > *w).unwrap_or(true) {
+      best = Some((weight, url));
+    }
+  }
+  best.map(|(_, url)| url)
+}
+
+// The first srcset-like attribute of the image, plus those of the <source> siblings inside a <picture>
+fn image_srcsets(elem: &ElementRef) -> Vec<String> {
+  let mut srcsets: Vec<String> = LAZY_SRCSET_ATTRS.iter().find_map(|name| non_empty_attr(elem, name)).into_iter().collect();
+  let picture = elem.parent().and_then(ElementRef::wrap).filter(|el| el.value().name() == "picture");
+  if let Some(picture) = picture {
+    let sources = picture.children().filter_map(ElementRef::wrap).filter(|el| el.value().name() == "source");
+    srcsets.extend(sources.filter_map(|source| LAZY_SRCSET_ATTRS.iter().find_map(|name| non_empty_attr(&source, name))));
+  }
+  srcsets
+}
+
+fn resolve_image_src(elem: &ElementRef) -> Option<String> {
+  LAZY_SRC_ATTRS.iter().find_map(|name| non_empty_attr(elem, name))
+    .or_else(|| best_srcset_candidate(image_srcsets(elem).iter().map(|ss| ss.as_str())))
+    .or_else(|| non_empty_attr(elem, "src"))
+}
+
+fn guess_mime_type(src: &str) -> Option<String> {
+  let path = src.split(['?', '#']).next().unwrap_or("");
+  let ext = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase())?;
+  MIME_TYPES.iter().find(|(e, _)| *e == ext.as_str()).map(|(_, mime)| mime.to_string())
+}
+
+fn find_caption(elem: &ElementRef) -> Option<String> {
+  let figure = elem.ancestors().filter_map(ElementRef::wrap).find(|el| el.value().name() == "figure")?;
+  let sel = Selector::parse("figcaption").ok()?;
+  figure.select(&sel).next()
+    .map(|fc| collapse_whitespace(&fc.text().collect::<Vec<&str>>().join(" ")))
+    .filter(|c| !c.is_empty())
+}
+
+fn first_source(elem: &ElementRef) -> Option<(String, Option<String>)> {
+  let sel = Selector::parse("source").ok()?;
+  elem.select(&sel).find_map(|source| {
+    non_empty_attr(&source, "src").or_else(|| non_empty_attr(&source, "data-src"))
+      .map(|src| (src, non_empty_attr(&source, "type")))
+  })
+}
+
+fn to_media_item(elem: &ElementRef, base_uri: &str) -> Option<MediaItem> {
+  let tag = elem.value().name();
+  let (kind, src, declared_type) = match tag {
+    "img" => ("image", resolve_image_src(elem)?, None),
+    "video" | "audio" => {
+      let (src, mime) = non_empty_attr(elem, "src").or_else(|| non_empty_attr(elem, "data-src")).map(|s| (s, None)).or_else(|| first_source(elem))?;
+      (tag, src, mime)
+    },
+    "iframe" | "embed" => ("embed", non_empty_attr(elem, "src").or_else(|| non_empty_attr(elem, "data-src"))?, non_empty_attr(elem, "type")),
+    _ => return None
+  };
+  let src = resolve_uri(&src, base_uri)?;
+  let mime_type = declared_type.or_else(|| guess_mime_type(&src));
+  Some(MediaItem {
+    kind: kind.to_string(),
+    alt: non_empty_attr(elem, "alt").or_else(|| non_empty_attr(elem, "title")),
+    caption: find_caption(elem),
+    width: dimension_attr(elem, "width"),
+    height: dimension_attr(elem, "height"),
+    mime_type,
+    poster: non_empty_attr(elem, "poster").and_then(|p| resolve_uri(&p, base_uri)),
+    src,
+  })
+}
+
+// Inventory of images, video, audio and embeds within the elements matched by the content selector
+pub fn extract_media(html_obj: &Html, selector_str: &str, uri: &str) -> Vec<MediaItem> {
+  let base_uri = html_base_uri(html_obj, uri);
+  let mut items: Vec<MediaItem> = vec![];
+  if let (Ok(root_sel), Ok(media_sel)) = (Selector::parse(selector_str), Selector::parse(EMBEDDED_MEDIA_SELECTOR)) {
+    for root in html_obj.select(&root_sel) {
+      for elem in root.select(&media_sel) {
+        if let Some(item) = to_media_item(&elem, &base_uri) {
+          if !items.iter().any(|mi| mi.src == item.src) {
+            items.push(item);
+          }
+        }
+      }
+    }
+  }
+  items
+}
+
+#[cfg(test)]
+mod tests {
+  use scraper::Html;
+  use crate::media::{MediaItem, best_srcset_candidate, extract_media};
+
+  #[test]
+  fn test_srcset_keeps_commas_inside_urls() {
+    let srcset = "https://cdn.example.com/img/w_400,h_300/cat.jpg 400w, https://cdn.example.com/img/w_800,h_600/cat.jpg 800w,/small.jpg";
+    assert_eq!(best_srcset_candidate([srcset].into_iter()).as_deref(), Some("https://cdn.example.com/img/w_800,h_600/cat.jpg"));
+  }
+
+  #[test]
+  fn test_srcset_prefers_highest_density() {
+    assert_eq!(best_srcset_candidate(["a.jpg, b.jpg 2x"].into_iter()).as_deref(), Some("b.jpg"));
+  }
+
+  fn main_media() -> Vec<MediaItem> {
+    let html = Html::parse_document(r#"<main>
+      <figure><picture><source srcset="/img/hero-640.webp 640w, /img/hero-1280.webp 1280w" type="image/webp">
+        <img src="/img/hero.jpg" alt="Hero"></picture><figcaption> The  hero </figcaption></figure>
+      <img src="data:image/gif;base64,R0lGOD" data-src="/img/lazy.png" width="300px">
+      <video poster="/img/poster.jpg"><source src="/media/clip.webm" type="video/webm"></video>
+      </main>"#);
+    let items = extract_media(&html, "main", "https://example.com/page");
+    assert_eq!(items.len(), 3);
+    items
+  }
+
+  #[test]
+  fn test_picture_sources_and_figure_caption() {
+    let items = main_media();
+    assert_eq!(items[0].src, "https://example.com/img/hero-1280.webp");
+    assert_eq!(items[0].caption.as_deref(), Some("The hero"));
+    assert_eq!(items[0].alt.as_deref(), Some("Hero"));
+  }
+
+  #[test]
+  fn test_lazy_image_replaces_placeholder() {
+    let items = main_media();
+    assert_eq!(items[1].src, "https://example.com/img/lazy.png");
+    assert_eq!(items[1].width, Some(300));
+    assert_eq!(items[1].mime_type.as_deref(), Some("image/png"));
+  }
+
+  #[test]
+  fn test_video_sources_and_poster() {
+    let items = main_media();
+    assert_eq!(items[2].kind, "video");
+    assert_eq!(items[2].src, "https://example.com/media/clip.webm");
+    assert_eq!(items[2].mime_type.as_deref(), Some("video/webm"));
+    assert_eq!(items[2].poster.as_deref(), Some("https://example.com/img/poster.jpg"));
+  }
+
+  #[test]
+  fn test_media_resolves_against_base_href() {
+    let html = Html::parse_document(r#"<html><head><base href="https://cdn.example.com/assets/"></head>
+      <body><main><img src="photo.jpg"><img src="mailto:someone@example.com"></main></body></html>"#);
+    let items = extract_media(&html, "main", "https://example.com/page");
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].src, "https://cdn.example.com/assets/photo.jpg");
+  }
+}
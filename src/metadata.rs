@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Attr, Name, Predicate};
+use serde::Serialize;
+use serde_json::Value;
+use crate::uris::{document_base_uri, resolve_uri};
+use crate::cleantext::collapse_whitespace;
+
+const OPEN_GRAPH_PREFIXES: [&str; 7] = ["og:", "article:", "book:", "profile:", "music:", "video:", "product:"];
+// plain application/json alternates are usually REST APIs such as /wp-json/ rather than feeds
+const FEED_TYPES: [(&str, &str); 3] = [
+  ("application/rss+xml", "rss"),
+  ("application/atom+xml", "atom"),
+  ("application/feed+json", "json"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedLink {
+  pub uri: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+  pub kind: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MicrodataItem {
+  #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+  pub item_type: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  // microdata (itemscope/itemprop) or rdfa (typeof/property)
+  pub syntax: String,
+  pub properties: BTreeMap<String, Vec<Value>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ItemSyntax {
+  Microdata,
+  Rdfa,
+}
+
+impl ItemSyntax {
+  fn scope_attr(&self) -> &'static str {
+    match self {
+      ItemSyntax::Microdata => "itemscope",
+      ItemSyntax::Rdfa => "typeof",
+    }
+  }
+
+  fn prop_attr(&self) -> &'static str {
+    match self {
+      ItemSyntax::Microdata => "itemprop",
+      ItemSyntax::Rdfa => "property",
+    }
+  }
+
+  fn type_attr(&self) -> &'static str {
+    match self {
+      ItemSyntax::Microdata => "itemtype",
+      ItemSyntax::Rdfa => "typeof",
+    }
+  }
+
+  fn id_attr(&self) -> &'static str {
+    match self {
+      ItemSyntax::Microdata => "itemid",
+      ItemSyntax::Rdfa => "resource",
+    }
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      ItemSyntax::Microdata => "microdata",
+      ItemSyntax::Rdfa => "rdfa",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct PageMetadata {
+  #[serde(rename = "openGraph", skip_serializing_if = "BTreeMap::is_empty")]
+  pub open_graph: BTreeMap<String, Value>,
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  pub twitter: BTreeMap<String, Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub canonical: Option<String>,
+  #[serde(rename = "jsonLd", skip_serializing_if = "Vec::is_empty")]
+  pub json_ld: Vec<Value>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub items: Vec<MicrodataItem>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub favicon: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub feeds: Vec<FeedLink>,
+}
+
+impl PageMetadata {
+  pub fn new(doc: &Document, uri: &str) -> PageMetadata {
+    let base_uri = document_base_uri(doc, uri);
+    PageMetadata {
+      open_graph: extract_prefixed_meta(doc, "property", &OPEN_GRAPH_PREFIXES),
+      twitter: extract_twitter_meta(doc),
+      canonical: extract_link_href(doc, "canonical").and_then(|href| resolve_uri(&href, &base_uri)),
+      json_ld: extract_json_ld(doc),
+      items: extract_items(doc),
+      favicon: extract_favicon(doc).and_then(|href| resolve_uri(&href, &base_uri)),
+      feeds: extract_feed_links(doc, &base_uri),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.open_graph.is_empty() && self.twitter.is_empty() && self.canonical.is_none() && self.json_ld.is_empty()
+      && self.items.is_empty() && self.favicon.is_none() && self.feeds.is_empty()
+  }
+
+  // JSON-LD objects whose @type matches any of the given schema.org types
+  pub fn json_ld_of_type(&self, types: &[&str]) -> Vec<&Value> {
+    self.json_ld.iter().filter(|item| json_ld_has_type(item, types)).collect()
+  }
+}
+
+pub fn json_ld_has_type(item: &Value, types: &[&str]) -> bool {
+  match item.get("@type") {
+    Some(Value::String(tp)) => types.contains(&tp.as_str()),
+    Some(Value::Array(tps)) => tps.iter().any(|tp| tp.as_str().map(|s| types.contains(&s)).unwrap_or(false)),
+    _ => false,
+  }
+}
+
+pub fn has_rel(node: &Node, rel: &str) -> bool {
+  node.attr("rel").map(|r| r.split_whitespace().any(|part| part.eq_ignore_ascii_case(rel))).unwrap_or(false)
+}
+
+fn insert_meta_value(map: &mut BTreeMap<String, Value>, key: &str, content: &str) {
+  let value = Value::String(content.trim().to_string());
+  match map.get_mut(key) {
+    Some(Value::Array(values)) => values.push(value),
+    Some(existing) => {
+      let first = existing.clone();
+      *existing = Value::Array(vec![first, value]);
+    },
+    None => {
+      map.insert(key.to_string(), value);
+    }
+  }
+}
+
+fn extract_prefixed_meta(doc: &Document, ref_field: &str, prefixes: &[&str]) -> BTreeMap<String, Value> {
+  let mut map: BTreeMap<String, Value> = BTreeMap::new();
+  for element in doc.find(Name("meta").and(Attr(ref_field, ()))) {
+    if let (Some(key), Some(content)) = (element.attr(ref_field), element.attr("content")) {
+      let key = key.trim().to_lowercase();
+      if prefixes.iter().any(|prefix| key.starts_with(prefix)) {
+        insert_meta_value(&mut map, &key, content);
+      }
+    }
+  }
+  map
+}
+
+fn extract_twitter_meta(doc: &Document) -> BTreeMap<String, Value> {
+  // twitter cards are declared with name, but some publishers use property
+  let mut map = extract_prefixed_meta(doc, "name", &["twitter:"]);
+  for (key, value) in extract_prefixed_meta(doc, "property", &["twitter:"]) {
+    map.entry(key).or_insert(value);
+  }
+  map
+}
+
+pub fn extract_link_href(doc: &Document, rel: &str) -> Option<String> {
+  doc.find(Name("link")).find(|el| has_rel(el, rel)).and_then(|el| el.attr("href")).map(|href| href.trim().to_string())
+}
+
+fn extract_favicon(doc: &Document) -> Option<String> {
+  let icons = doc.find(Name("link")).filter(|el| has_rel(el, "icon") || has_rel(el, "apple-touch-icon")).collect::<Vec<Node>>();
+  let preferred = icons.iter().find(|el| has_rel(el, "icon")).or(icons.first());
+  preferred.and_then(|el| el.attr("href")).map(|href| href.trim().to_string())
+}
+
+fn extract_feed_links(doc: &Document, base_uri: &str) -> Vec<FeedLink> {
+  let mut feeds: Vec<FeedLink> = vec![];
+  for element in doc.find(Name("link")).filter(|el| has_rel(el, "alternate")) {
+    let mime = element.attr("type").unwrap_or("").trim().to_lowercase();
+    if let Some((_, kind)) = FEED_TYPES.iter().find(|(tp, _)| *tp == mime.as_str()) {
+      if let Some(uri) = element.attr("href").and_then(|href| resolve_uri(href, base_uri)) {
+        if !feeds.iter().any(|fl| fl.uri == uri) {
+          let title = element.attr("title").map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+          feeds.push(FeedLink { uri, title, kind: kind.to_string() });
+        }
+      }
+    }
+  }
+  feeds
+}
+
+fn extract_json_ld(doc: &Document) -> Vec<Value> {
+  let mut items: Vec<Value> = vec![];
+  for element in doc.find(Name("script").and(Attr("type", "application/ld+json"))) {
+    if let Ok(value) = serde_json::from_str::<Value>(element.text().trim()) {
+      push_json_ld(&mut items, value);
+    }
+  }
+  items
+}
+
+// Flatten top-level arrays and @graph collections into individual objects
+fn push_json_ld(items: &mut Vec<Value>, value: Value) {
+  match value {
+    Value::Array(values) => {
+      for v in values {
+        push_json_ld(items, v);
+      }
+    },
+    Value::Object(ref obj) => {
+      if let Some(Value::Array(graph)) = obj.get("@graph") {
+        for v in graph.clone() {
+          push_json_ld(items, v);
+        }
+      } else {
+        items.push(value);
+      }
+    },
+    _ => ()
+  }
+}
+
+fn extract_items(doc: &Document) -> Vec<MicrodataItem> {
+  let mut items: Vec<MicrodataItem> = vec![];
+  for syntax in [ItemSyntax::Microdata, ItemSyntax::Rdfa] {
+    let top_level = doc.find(Attr(syntax.scope_attr(), ())).filter(|el| el.attr(syntax.prop_attr()).is_none());
+    for element in top_level {
+      items.push(read_item(&element, syntax));
+    }
+  }
+  items
+}
+
+fn read_item(element: &Node, syntax: ItemSyntax) -> MicrodataItem {
+  let mut properties: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+  collect_item_properties(element, syntax, &mut properties);
+  MicrodataItem {
+    item_type: element.attr(syntax.type_attr()).map(|t| t.trim().to_string()).filter(|t| !t.is_empty()),
+    id: element.attr(syntax.id_attr()).map(|t| t.trim().to_string()),
+    syntax: syntax.name().to_string(),
+    properties,
+  }
+}
+
+fn collect_item_properties(parent: &Node, syntax: ItemSyntax, properties: &mut BTreeMap<String, Vec<Value>>) {
+  for child in parent.children().filter(|c| c.name().is_some()) {
+    let is_scope = child.attr(syntax.scope_attr()).is_some();
+    if let Some(prop_names) = child.attr(syntax.prop_attr()) {
+      let value = if is_scope {
+        serde_json::to_value(read_item(&child, syntax)).unwrap_or(Value::Null)
+      } else {
+        Value::String(item_property_value(&child))
+      };
+      for name in prop_names.split_whitespace() {
+        properties.entry(name.to_string()).or_default().push(value.clone());
+      }
+    }
+    // nested scopes own their own properties
+    if !is_scope {
+      collect_item_properties(&child, syntax, properties);
+    }
+  }
+}
+
+fn item_property_value(node: &Node) -> String {
+  let attr_name = match node.name().unwrap_or("") {
+    "meta" => "content",
+    "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => "src",
+    "a" | "area" | "link" => "href",
+    "object" => "data",
+    "data" | "meter" => "value",
+    "time" => "datetime",
+    _ => "content",
+  };
+  node.attr(attr_name).or(node.attr("content")).map(|v| v.trim().to_string())
+    .unwrap_or_else(|| collapse_whitespace(&node.text()))
+}
+
+#[cfg(test)]
+mod tests {
+  use select::document::Document;
+  use serde_json::json;
+  use crate::metadata::PageMetadata;
+
+  const PAGE_URI: &str = "https://example.com/reviews/kettle?ref=home";
+
+  fn metadata_of(html: &str) -> PageMetadata {
+    PageMetadata::new(&Document::from(html), PAGE_URI)
+  }
+
+  #[test]
+  fn test_open_graph_and_twitter() {
+    let metadata = metadata_of(r#"<html><head>
+      <meta property="og:title" content="Kettle review" /><meta property="og:image" content="/a.jpg" /><meta property="og:image" content="/b.jpg" />
+      <meta name="twitter:card" content="summary" /><meta property="twitter:site" content="@example" /></head></html>"#);
+    assert_eq!(metadata.open_graph.get("og:title"), Some(&json!("Kettle review")));
+    assert_eq!(metadata.open_graph.get("og:image"), Some(&json!(["/a.jpg", "/b.jpg"])));
+    assert_eq!(metadata.twitter.get("twitter:card"), Some(&json!("summary")));
+    assert_eq!(metadata.twitter.get("twitter:site"), Some(&json!("@example")));
+  }
+
+  #[test]
+  fn test_link_tags_resolve_against_page() {
+    let metadata = metadata_of(r#"<html><head>
+      <link rel="canonical" href="/reviews/kettle" /><link rel="icon" href="/favicon.ico" />
+      <link rel="alternate" type="application/rss+xml" title="News" href="/feed.xml" />
+      <link rel="alternate" type="application/json" href="/wp-json/wp/v2/posts/1" /></head></html>"#);
+    assert_eq!(metadata.canonical.as_deref(), Some("https://example.com/reviews/kettle"));
+    assert_eq!(metadata.favicon.as_deref(), Some("https://example.com/favicon.ico"));
+    assert_eq!(metadata.feeds.len(), 1);
+    assert_eq!(metadata.feeds[0].kind, "rss");
+  }
+
+  #[test]
+  fn test_json_ld_graph() {
+    let metadata = metadata_of(r#"<html><head>
+      <script type="application/ld+json">{"@context": "https://schema.org", "@graph": [{"@type": "Article", "headline": "Kettle review"}, {"@type": "Organization"}]}</script>
+      </head></html>"#);
+    assert_eq!(metadata.json_ld.len(), 2);
+    assert_eq!(metadata.json_ld_of_type(&["Article"]).len(), 1);
+  }
+
+  #[test]
+  fn test_nested_microdata() {
+    let metadata = metadata_of(r#"<html><body><div itemscope itemtype="https://schema.org/Product"><span itemprop="name">Kettle</span>
+      <div itemprop="offers" itemscope itemtype="https://schema.org/Offer"><meta itemprop="price" content="24.50" /></div></div></body></html>"#);
+    let product = &metadata.items[0];
+    assert_eq!(product.syntax, "microdata");
+    assert_eq!(product.properties.get("name"), Some(&vec![json!("Kettle")]));
+    assert_eq!(product.properties["offers"][0]["properties"]["price"], json!(["24.50"]));
+  }
+}
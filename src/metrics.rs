@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use string_patterns::*;
+
+// average adult silent reading speed
+const WORDS_PER_MINUTE: f64 = 238.0;
+const VOWELS: &str = "aeiouyàáâãäåèéêëìíîïòóôõöùúûüýÿæœ";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readability {
+  pub formula: String,
+  pub score: f64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub grade: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadingMetrics {
+  pub words: usize,
+  pub sentences: usize,
+  pub paragraphs: usize,
+  pub syllables: usize,
+  #[serde(rename = "readingTimeSecs")]
+  pub reading_time_secs: u64,
+  #[serde(rename = "readingTimeMins")]
+  pub reading_time_mins: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub readability: Option<Readability>,
+  // language the readability formula was chosen for
+  #[serde(skip)]
+  lang: String,
+}
+
+impl ReadingMetrics {
+  // Counts are taken from plain text with blank lines between paragraphs, as rendered by render_plain_text
+  pub fn new(text: &str, lang: Option<&str>) -> ReadingMetrics {
+    let lang_code = lang.unwrap_or("en").to_lowercase();
+    let english = lang_code == "en";
+    let words = extract_words(text);
+    let num_words = words.len();
+    let sentences = count_sentences(text);
+    let paragraphs = text.split("\n\n").filter(|p| p.chars().any(|c| c.is_alphanumeric())).count();
+    let syllables = words.iter().map(|w| count_syllables(w, english)).sum::<usize>();
+    let reading_time_secs = (num_words as f64 / WORDS_PER_MINUTE * 60.0).round() as u64;
+    ReadingMetrics {
+      words: num_words,
+      sentences,
+      paragraphs,
+      syllables,
+      reading_time_secs,
+      reading_time_mins: reading_time_secs.div_ceil(60),
+      readability: calculate_readability(&lang_code, num_words, sentences, syllables),
+      lang: lang_code,
+    }
+  }
+
+  // Add the counts of a following page and rescore the combined text
+  pub fn append(&mut self, next: &ReadingMetrics) {
+    self.words += next.words;
+    self.sentences += next.sentences;
+    self.paragraphs += next.paragraphs;
+    self.syllables += next.syllables;
+    self.reading_time_secs = (self.words as f64 / WORDS_PER_MINUTE * 60.0).round() as u64;
+    self.reading_time_mins = self.reading_time_secs.div_ceil(60);
+    self.readability = calculate_readability(&self.lang, self.words, self.sentences, self.syllables);
+  }
+}
+
+fn extract_words(text: &str) -> Vec<String> {
+  text.split_whitespace()
+    .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+    .filter(|w| w.chars().any(|c| c.is_alphabetic()))
+    .collect()
+}
+
+fn count_sentences(text: &str) -> usize {
+  text.to_string().pattern_split(r"[.!?…。]+[\s\)\]\x22”’]*|\n\n", true)
+    .iter()
+    .filter(|s| s.chars().filter(|c| c.is_alphabetic()).count() > 1)
+    .count()
+}
+
+// Approximate syllables as vowel groups, ignoring a silent final e in English
+pub fn count_syllables(word: &str, english: bool) -> usize {
+  let chars = word.chars().collect::<Vec<char>>();
+  let mut count = 0;
+  let mut prev_vowel = false;
+  for c in chars.iter() {
+    let is_vowel = VOWELS.contains(*c);
+    if is_vowel && !prev_vowel {
+      count += 1;
+    }
+    prev_vowel = is_vowel;
+  }
+  let len = chars.len();
+  if english && len > 2 && chars[len - 1] == 'e' && chars[len - 2] != 'l' && !VOWELS.contains(chars[len - 2]) && count > 1 {
+    count -= 1;
+  }
+  count.max(1)
+}
+
+fn round_score(score: f64) -> f64 {
+  (score * 10.0).round() / 10.0
+}
+
+// Flesch reading ease and its language-specific adaptations
+fn calculate_readability(lang: &str, words: usize, sentences: usize, syllables: usize) -> Option<Readability> {
+  if words < 1 || sentences < 1 {
+    return None;
+  }
+  let asl = words as f64 / sentences as f64;
+  let asw = syllables as f64 / words as f64;
+  let (formula, score) = match lang {
+    "en" => ("flesch", 206.835 - 1.015 * asl - 84.6 * asw),
+    "de" => ("amstad", 180.0 - asl - 58.5 * asw),
+    "es" => ("fernandez_huerta", 206.84 - 60.0 * asw - 1.02 * asl),
+    "fr" => ("kandel_moles", 207.0 - 1.015 * asl - 73.6 * asw),
+    "it" => ("flesch_vacca", 217.0 - 1.3 * asl - 60.0 * asw),
+    "nl" => ("douma", 206.835 - 0.93 * asl - 77.0 * asw),
+    "pt" => ("flesch_martins", 248.835 - 1.015 * asl - 84.6 * asw),
+    _ => return None
+  };
+  let grade = if lang == "en" {
+    Some(round_score(0.39 * asl + 11.8 * asw - 15.59))
+  } else {
+    None
+  };
+  Some(Readability {
+    formula: formula.to_string(),
+    score: round_score(score),
+    grade,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::metrics::{ReadingMetrics, count_syllables};
+
+  const TEXT: &str = "The cat sat on the mat. It was happy!\n\nThen it left.";
+
+  #[test]
+  fn test_count_syllables() {
+    assert_eq!(count_syllables("table", true), 2);
+    assert_eq!(count_syllables("make", true), 1);
+    assert_eq!(count_syllables("reading", true), 2);
+  }
+
+  #[test]
+  fn test_text_counts() {
+    let metrics = ReadingMetrics::new(TEXT, Some("en"));
+    assert_eq!(metrics.words, 12);
+    assert_eq!(metrics.sentences, 3);
+    assert_eq!(metrics.paragraphs, 2);
+  }
+
+  #[test]
+  fn test_readability_needs_supported_language() {
+    assert!(ReadingMetrics::new(TEXT, Some("en")).readability.is_some());
+    assert!(ReadingMetrics::new(TEXT, Some("ja")).readability.is_none());
+  }
+
+  #[test]
+  fn test_append_stitched_page() {
+    let metrics = ReadingMetrics::new(TEXT, Some("en"));
+    let mut stitched = ReadingMetrics::new(TEXT, Some("en"));
+    stitched.append(&ReadingMetrics::new("A second page follows here.", Some("en")));
+    assert_eq!(stitched.words, 17);
+    assert_eq!(stitched.sentences, 4);
+    assert_eq!(stitched.readability.map(|r| r.formula), metrics.readability.map(|r| r.formula));
+  }
+}
@@ -0,0 +1,299 @@
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use serde_json::Value;
+use crate::metadata::PageMetadata;
+use crate::stats::PageElement;
+use crate::uris::resolve_uri;
+use crate::cleantext::collapse_whitespace;
+
+const BREADCRUMB_SELECTOR: &str = r#"[aria-label*="readcrumb" i],[class*="breadcrumb" i],[id*="breadcrumb" i]"#;
+const FALLBACK_NAV_SELECTOR: &str = r#"nav,[role="navigation"]"#;
+// menus are mostly link text
+const MAX_MENU_PLAIN_TEXT_RATIO: f64 = 0.5;
+const MAX_MENU_CANDIDATES: usize = 12;
+const MAX_NAV_DEPTH: usize = 6;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NavItem {
+  pub label: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub uri: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub children: Vec<NavItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreadcrumbItem {
+  pub position: usize,
+  pub label: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub uri: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Breadcrumbs {
+  // jsonld, microdata or markup
+  pub source: String,
+  pub items: Vec<BreadcrumbItem>,
+}
+
+fn element_text(elem: &ElementRef) -> String {
+  collapse_whitespace(&elem.text().collect::<Vec<&str>>().join(" "))
+}
+
+fn is_list(elem: &ElementRef) -> bool {
+  matches!(elem.value().name(), "ul" | "ol" | "menu")
+}
+
+fn has_breadcrumb_hint(elem: &ElementRef) -> bool {
+  ["class", "id", "aria-label"].iter()
+    .filter_map(|name| elem.value().attr(name))
+    .any(|v| v.to_lowercase().contains("breadcrumb"))
+}
+
+fn resolve_href(elem: &ElementRef, base_uri: &str) -> Option<String> {
+  elem.value().attr("href").map(|href| href.trim())
+    .filter(|href| !href.is_empty() && !href.starts_with('#') && !href.starts_with("javascript:"))
+    .and_then(|href| resolve_uri(href, base_uri))
+}
+
+// Text of a list item excluding its nested lists
+fn own_text(elem: &ElementRef) -> String {
+  let mut parts: Vec<String> = vec![];
+  for child in elem.children() {
+    if let Some(child_elem) = ElementRef::wrap(child) {
+      if !is_list(&child_elem) {
+        parts.push(element_text(&child_elem));
+      }
+    } else if let Some(text) = child.value().as_text() {
+      parts.push(text.to_string());
+    }
+  }
+  collapse_whitespace(&parts.join(" "))
+}
+
+// The first link that is not inside a nested list
+fn own_link<'a>(elem: &ElementRef<'a>) -> Option<ElementRef<'a>> {
+  for child in elem.children().filter_map(ElementRef::wrap) {
+    if child.value().name() == "a" {
+      return Some(child);
+    }
+    if !is_list(&child) {
+      if let Some(link) = own_link(&child) {
+        return Some(link);
+      }
+    }
+  }
+  None
+}
+
+fn child_lists<'a>(elem: &ElementRef<'a>) -> Vec<ElementRef<'a>> {
+  let mut lists = vec![];
+  for child in elem.children().filter_map(ElementRef::wrap) {
+    if is_list(&child) {
+      lists.push(child);
+    } else if child.value().name() != "a" {
+      lists.extend(child_lists(&child));
+    }
+  }
+  lists
+}
+
+fn build_list_items(list: &ElementRef, base_uri: &str, depth: usize) -> Vec<NavItem> {
+  let mut items: Vec<NavItem> = vec![];
+  for li in list.children().filter_map(ElementRef::wrap).filter(|el| el.value().name() == "li") {
+    let link = own_link(&li);
+    let label = link.as_ref().map(element_text).filter(|l| !l.is_empty()).unwrap_or_else(|| own_text(&li));
+    let children = if depth < MAX_NAV_DEPTH {
+      child_lists(&li).iter().flat_map(|sub| build_list_items(sub, base_uri, depth + 1)).collect()
+    } else {
+      vec![]
+    };
+    if !label.is_empty() || !children.is_empty() {
+      items.push(NavItem {
+        label,
+        uri: link.and_then(|a| resolve_href(&a, base_uri)),
+        children,
+      });
+    }
+  }
+  items
+}
+
+// Build a link tree from the lists within a menu element, or a flat list of its links
+pub fn build_nav_tree(root: &ElementRef, base_uri: &str) -> Vec<NavItem> {
+  let lists = if is_list(root) { vec![*root] } else { child_lists(root) };
+  let items = lists.iter().flat_map(|list| build_list_items(list, base_uri, 0)).collect::<Vec<NavItem>>();
+  if !items.is_empty() {
+    return items;
+  }
+  let a_sel = Selector::parse("a").unwrap();
+  root.select(&a_sel).filter_map(|a| {
+    let label = element_text(&a);
+    if label.is_empty() {
+      None
+    } else {
+      Some(NavItem { label, uri: resolve_href(&a, base_uri), children: vec![] })
+    }
+  }).collect()
+}
+
+fn is_menu_hint(element: &PageElement) -> bool {
+  ["nav", "header", "menu"].contains(&element.tag_name.as_str())
+    || element.id.iter().chain(element.class_names.iter()).any(|name| {
+      let name = name.to_lowercase();
+      name.contains("nav") || name.contains("menu")
+    })
+}
+
+// The primary navigation from the ranked menu-like elements, preferring those marked up as navigation.
+// Returns the menu tree and the selector of the element it was read from
+pub fn extract_navigation(html_obj: &Html, menu_elements: &[PageElement], base_uri: &str) -> (Vec<NavItem>, Option<String>) {
+  let candidates = menu_elements.iter()
+    .filter(|el| el.plain_text_ratio() <= MAX_MENU_PLAIN_TEXT_RATIO)
+    .take(MAX_MENU_CANDIDATES)
+    .collect::<Vec<&PageElement>>();
+  let mut selectors = candidates.iter().filter(|el| is_menu_hint(el)).map(|el| el.selector()).collect::<Vec<String>>();
+  selectors.extend(candidates.iter().filter(|el| !is_menu_hint(el)).map(|el| el.selector()));
+  selectors.push(FALLBACK_NAV_SELECTOR.to_string());
+  for str_sel in selectors {
+    if let Ok(sel) = Selector::parse(&str_sel) {
+      for root in html_obj.select(&sel).filter(|el| !has_breadcrumb_hint(el)) {
+        let items = build_nav_tree(&root, base_uri);
+        if items.len() > 1 {
+          return (items, Some(str_sel.clone()));
+        }
+      }
+    }
+  }
+  (vec![], None)
+}
+
+fn json_ld_text(value: &Value) -> Option<String> {
+  match value {
+    Value::String(s) => Some(collapse_whitespace(s)).filter(|s| !s.is_empty()),
+    Value::Number(n) => Some(n.to_string()),
+    _ => None
+  }
+}
+
+fn json_ld_breadcrumbs(metadata: &PageMetadata, base_uri: &str) -> Vec<BreadcrumbItem> {
+  let mut items: Vec<BreadcrumbItem> = vec![];
+  for list in metadata.json_ld_of_type(&["BreadcrumbList"]) {
+    let elements = match list.get("itemListElement") {
+      Some(Value::Array(elements)) => elements.clone(),
+      Some(other) => vec![other.clone()],
+      None => vec![]
+    };
+    for (index, element) in elements.iter().enumerate() {
+      // the item may be a plain IRI or a Thing with its own @id and name
+      let item = element.get("item");
+      let label = element.get("name").and_then(json_ld_text)
+        .or_else(|| item.and_then(|it| it.get("name")).and_then(json_ld_text));
+      let uri = item.and_then(|it| json_ld_text(it).or_else(|| it.get("@id").and_then(json_ld_text)).or_else(|| it.get("url").and_then(json_ld_text)));
+      let position = element.get("position").and_then(|p| p.as_u64().or_else(|| p.as_str().and_then(|s| s.parse::<u64>().ok())))
+        .map(|p| p as usize).unwrap_or(index + 1);
+      if let Some(label) = label {
+        items.push(BreadcrumbItem { position, label, uri: uri.and_then(|u| resolve_uri(&u, base_uri)) });
+      }
+    }
+    if !items.is_empty() {
+      break;
+    }
+  }
+  items.sort_by_key(|item| item.position);
+  items
+}
+
+fn first_property(properties: &Value, name: &str) -> Option<Value> {
+  properties.get(name).and_then(|values| values.as_array()).and_then(|values| values.first().cloned())
+}
+
+fn microdata_breadcrumbs(metadata: &PageMetadata, base_uri: &str) -> Vec<BreadcrumbItem> {
+  let mut items: Vec<BreadcrumbItem> = vec![];
+  let lists = metadata.items.iter().filter(|item| item.item_type.as_ref().map(|tp| tp.ends_with("BreadcrumbList")).unwrap_or(false));
+  for list in lists {
+    for (index, element) in list.properties.get("itemListElement").cloned().unwrap_or_default().iter().enumerate() {
+      let properties = element.get("properties").cloned().unwrap_or(Value::Null);
+      let item = first_property(&properties, "item");
+      // a nested item scope carries the name and id itself
+      let label = first_property(&properties, "name").as_ref().and_then(json_ld_text)
+        .or_else(|| item.as_ref().and_then(|it| it.get("properties")).and_then(|p| first_property(p, "name")).as_ref().and_then(json_ld_text));
+      let uri = item.as_ref().and_then(|it| json_ld_text(it).or_else(|| it.get("id").and_then(json_ld_text)));
+      let position = first_property(&properties, "position").as_ref().and_then(json_ld_text)
+        .and_then(|p| p.parse::<usize>().ok()).unwrap_or(index + 1);
+      if let Some(label) = label {
+        items.push(BreadcrumbItem { position, label, uri: uri.and_then(|u| resolve_uri(&u, base_uri)) });
+      }
+    }
+    if !items.is_empty() {
+      break;
+    }
+  }
+  items.sort_by_key(|item| item.position);
+  items
+}
+
+fn markup_breadcrumbs(html_obj: &Html, base_uri: &str) -> Vec<BreadcrumbItem> {
+  let sel = Selector::parse(BREADCRUMB_SELECTOR).unwrap();
+  let li_sel = Selector::parse("li").unwrap();
+  let a_sel = Selector::parse("a").unwrap();
+  for root in html_obj.select(&sel) {
+    let mut labelled: Vec<(String, Option<String>)> = root.select(&li_sel).map(|li| {
+      let link = li.select(&a_sel).next();
+      let label = link.as_ref().map(element_text).filter(|l| !l.is_empty()).unwrap_or_else(|| element_text(&li));
+      (label, link.and_then(|a| resolve_href(&a, base_uri)))
+    }).collect();
+    if labelled.is_empty() {
+      labelled = root.select(&a_sel).map(|a| (element_text(&a), resolve_href(&a, base_uri))).collect();
+    }
+    let items = labelled.into_iter().filter(|(label, _)| !label.is_empty())
+      .enumerate()
+      .map(|(index, (label, uri))| BreadcrumbItem { position: index + 1, label, uri })
+      .collect::<Vec<BreadcrumbItem>>();
+    if items.len() > 1 {
+      return items;
+    }
+  }
+  vec![]
+}
+
+// Breadcrumbs from structured data if available, otherwise from breadcrumb markup
+pub fn extract_breadcrumbs(html_obj: &Html, metadata: &PageMetadata, base_uri: &str) -> Option<Breadcrumbs> {
+  let sources: [(&str, Vec<BreadcrumbItem>); 2] = [
+    ("jsonld", json_ld_breadcrumbs(metadata, base_uri)),
+    ("microdata", microdata_breadcrumbs(metadata, base_uri)),
+  ];
+  sources.into_iter().find(|(_, items)| !items.is_empty())
+    .or_else(|| Some(("markup", markup_breadcrumbs(html_obj, base_uri))).filter(|(_, items)| !items.is_empty()))
+    .map(|(source, items)| Breadcrumbs { source: source.to_string(), items })
+}
+
+#[cfg(test)]
+mod tests {
+  use scraper::{Html, Selector};
+  use crate::metadata::PageMetadata;
+  use crate::navigation::{build_nav_tree, extract_breadcrumbs};
+
+  #[test]
+  fn test_nav_tree() {
+    let html = Html::parse_fragment(r#"<nav id="main-nav"><ul><li><a href="/news">News</a><ul><li><a href="/news/world">World</a></li><li><a href="/news/uk">UK</a></li></ul></li>
+      <li><a href="https://example.com/sport">Sport</a></li></ul></nav>"#);
+    let nav = html.select(&Selector::parse("nav").unwrap()).next().unwrap();
+    let items = build_nav_tree(&nav, "https://example.com");
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].label, "News");
+    assert_eq!(items[0].children.len(), 2);
+    assert_eq!(items[0].children[1].uri.as_deref(), Some("https://example.com/news/uk"));
+  }
+
+  #[test]
+  fn test_breadcrumbs_from_markup() {
+    let html = Html::parse_fragment(r#"<ol class="breadcrumbs"><li><a href="/">Home</a></li><li><a href="/news">News</a></li><li>Today</li></ol>"#);
+    let crumbs = extract_breadcrumbs(&html, &PageMetadata::default(), "https://example.com").unwrap();
+    assert_eq!(crumbs.source, "markup");
+    assert_eq!(crumbs.items.len(), 3);
+    assert_eq!(crumbs.items[2].label, "Today");
+    assert!(crumbs.items[2].uri.is_none());
+  }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use crate::plaintext::RenderedText;
+
+// A heading with its own section text and nested subsections.
+// start and end are character offsets in the rendered plain text spanning the heading and all its subsections,
+// and are left out when the main text is returned as HTML
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlineSection {
+  pub level: u8,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub anchor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub start: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub end: Option<usize>,
+  pub text: String,
+  #[serde(skip_serializing_if = "Vec::is_empty", default)]
+  pub children: Vec<OutlineSection>,
+}
+
+fn slice_chars(text: &str, start: usize, end: usize) -> String {
+  text.chars().skip(start).take(end.saturating_sub(start)).collect::<String>().trim().to_string()
+}
+
+pub fn to_anchor_slug(title: &str) -> String {
+  let slug = title.to_lowercase().chars().map(|c| if c.is_alphanumeric() { c } else { '-' }).collect::<String>();
+  slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<&str>>().join("-")
+}
+
+fn nest_sections(flat: &[OutlineSection], index: &mut usize, parent_level: u8) -> Vec<OutlineSection> {
+  let mut sections: Vec<OutlineSection> = vec![];
+  while let Some(item) = flat.get(*index) {
+    if item.level <= parent_level {
+      break;
+    }
+    *index += 1;
+    let mut section = item.clone();
+    section.children = nest_sections(flat, index, section.level);
+    sections.push(section);
+  }
+  sections
+}
+
+// Build a nested outline from the headings in the rendered main text.
+// Any text before the first heading is returned as a level 0 lead section
+pub fn build_outline(rendered: &RenderedText) -> Vec<OutlineSection> {
+  let text_len = rendered.text.chars().count();
+  let headings = &rendered.headings;
+  let mut flat: Vec<OutlineSection> = Vec::with_capacity(headings.len() + 1);
+  let first_start = headings.first().map(|hm| hm.start).unwrap_or(text_len);
+  let lead = slice_chars(&rendered.text, 0, first_start);
+  if !lead.is_empty() {
+    flat.push(OutlineSection { level: 0, title: None, anchor: None, start: Some(0), end: Some(first_start), text: lead, children: vec![] });
+  }
+  for (index, hm) in headings.iter().enumerate() {
+    let body_end = headings.get(index + 1).map(|next| next.start).unwrap_or(text_len);
+    let end = headings.iter().skip(index + 1).find(|next| next.level <= hm.level).map(|next| next.start).unwrap_or(text_len);
+    let anchor = hm.anchor.clone().or_else(|| Some(to_anchor_slug(&hm.title)).filter(|slug| !slug.is_empty()));
+    flat.push(OutlineSection {
+      level: hm.level,
+      title: Some(hm.title.clone()),
+      anchor,
+      start: Some(hm.start),
+      end: Some(end),
+      text: slice_chars(&rendered.text, hm.end, body_end),
+      children: vec![],
+    });
+  }
+  let mut index = 0;
+  let mut sections = vec![];
+  // the lead section has level 0 and cannot parent headings
+  if flat.first().map(|s| s.level == 0).unwrap_or(false) {
+    sections.push(flat[0].clone());
+    index = 1;
+  }
+  sections.extend(nest_sections(&flat, &mut index, 0));
+  sections
+}
+
+// Drop offsets that only apply to the plain text rendering
+pub fn clear_offsets(sections: &mut [OutlineSection]) {
+  for section in sections {
+    section.start = None;
+    section.end = None;
+    clear_offsets(&mut section.children);
+  }
+}
+
+// Move offsets along when the text is appended after other content
+pub fn shift_offsets(sections: &mut [OutlineSection], offset: usize) {
+  for section in sections {
+    section.start = section.start.map(|start| start + offset);
+    section.end = section.end.map(|end| end + offset);
+    shift_offsets(&mut section.children, offset);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::outline::build_outline;
+  use crate::plaintext::render_plain_text;
+
+  const HTML: &str = r#"<p>Intro text.</p><h2 id="setup">Setup</h2><p>Install it.</p><h3>Linux</h3><p>Use apt.</p>
+      <h2><div>Usage</div><div>notes</div></h2><p>Run it.</p>"#;
+
+  #[test]
+  fn test_sections_nest_by_heading_level() {
+    let outline = build_outline(&render_plain_text(HTML, None));
+    assert_eq!(outline.len(), 3);
+    assert_eq!(outline[0].level, 0);
+    assert_eq!(outline[0].text, "Intro text.");
+    let setup = &outline[1];
+    assert_eq!(setup.anchor.as_deref(), Some("setup"));
+    assert_eq!(setup.text, "Install it.");
+    assert_eq!(setup.children[0].title.as_deref(), Some("Linux"));
+    assert_eq!(setup.children[0].text, "Use apt.");
+  }
+
+  #[test]
+  fn test_section_offsets_span_children() {
+    let rendered = render_plain_text(HTML, None);
+    let outline = build_outline(&rendered);
+    let setup = &outline[1];
+    let text = rendered.text.chars().collect::<Vec<char>>();
+    assert_eq!(text[setup.start.unwrap()..setup.end.unwrap()].iter().collect::<String>().trim(), "Setup\n\nInstall it.\n\nLinux\n\nUse apt.");
+  }
+
+  #[test]
+  fn test_heading_with_block_children() {
+    let outline = build_outline(&render_plain_text(HTML, None));
+    let usage = &outline[2];
+    assert_eq!(usage.title.as_deref(), Some("Usage notes"));
+    assert_eq!(usage.anchor.as_deref(), Some("usage-notes"));
+    assert_eq!(usage.text, "Run it.");
+  }
+}
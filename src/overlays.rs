@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+use scraper::{ElementRef, Html, Selector};
+use html5ever::tree_builder::TreeSink;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use string_patterns::*;
+use crate::metadata::PageMetadata;
+use crate::regions::{RegionKind, region_hint};
+use crate::cleantext::collapse_whitespace;
+
+// consent management platforms and widely used cookie banner plugins
+const CMP_SELECTORS: [(&str, &str); 24] = [
+  ("onetrust", "#onetrust-consent-sdk,#onetrust-banner-sdk,#onetrust-pc-sdk"),
+  ("cookiebot", "#CybotCookiebotDialog,#CybotCookiebotDialogBodyUnderlay"),
+  ("usercentrics", "#usercentrics-root,#usercentrics-cmp-ui"),
+  ("quantcast", "#qc-cmp2-container,.qc-cmp2-container,#qcCmpUi"),
+  ("didomi", "#didomi-host,#didomi-notice,#didomi-popup"),
+  ("sourcepoint", r#"[id^="sp_message_container"],.sp_veil"#),
+  ("trustarc", "#truste-consent-track,#truste-consent-content,.truste_overlay,.truste_box_overlay"),
+  ("funding_choices", ".fc-consent-root,.fc-dialog-container"),
+  ("consentmanager", "#cmpbox,#cmpbox2,.cmpboxBG"),
+  ("cookieconsent", ".cc-window,.cc-banner,.cc-revoke"),
+  ("cookie_law_info", "#cookie-law-info-bar,#cliSettingsPopup,.cli-modal-backdrop"),
+  ("cookie_notice", "#cookie-notice"),
+  ("iubenda", "#iubenda-cs-banner,.iubenda-cs-container"),
+  ("osano", ".osano-cm-window,.osano-cm-dialog"),
+  ("termly", "#termly-code-snippet-support"),
+  ("klaro", "#klaro,.klaro"),
+  ("borlabs", "#BorlabsCookieBox"),
+  ("moove_gdpr", "#moove_gdpr_cookie_info_bar,#moove_gdpr_cookie_modal"),
+  ("complianz", "#cmplz-cookiebanner-container,.cmplz-cookiebanner"),
+  ("cookieyes", ".cky-consent-container,.cky-modal,.cky-overlay"),
+  ("civic", "#ccc,#ccc-overlay"),
+  ("axeptio", "#axeptio_overlay"),
+  ("tarteaucitron", "#tarteaucitronRoot"),
+  ("gdpr_consent_tool", "#gdpr-consent-tool-wrapper,#gdpr-consent-notice"),
+];
+// subscription overlays from metering platforms, removed without removing gated article containers
+const PAYWALL_OVERLAY_SELECTOR: &str = ".tp-modal,.tp-backdrop,.tp-container-inner,#piano-modal,.regwall,.pw-modal,.paywall-modal,.paywall-overlay,.subscription-modal";
+const PAYWALL_MARKER_SELECTOR: &str = r#"[class*="paywall" i],[id*="paywall" i],[data-paywall],.meteredContent,.piano-offer"#;
+const DIALOG_SELECTOR: &str = r#"[role="dialog"],[role="alertdialog"],[aria-modal="true"],[class*="modal" i],[class*="overlay" i],[class*="popup" i],[id*="modal" i],[id*="overlay" i],[id*="popup" i]"#;
+const DIALOG_ROLE_SELECTOR: &str = r#"[role="dialog"],[role="alertdialog"],[aria-modal="true"]"#;
+// wrappers named after modals or cookies may hold the page itself
+const CONTENT_SELECTOR: &str = "main,article,h1,h2,[role=\"main\"]";
+const MAX_PARAGRAPHS: usize = 3;
+const NOTICE_SELECTOR: &str = r#"[class*="cookie" i],[id*="cookie" i],[class*="consent" i],[id*="consent" i],[class*="gdpr" i],[id*="gdpr" i]"#;
+const CONSENT_TEXT_PATTERN: &str = r"(accept|allow|agree to)( all)? cookies|we use cookies|(cookie|privacy) (settings|preferences)|manage (your )?(consent|preferences)|consent to (the use of )?cookies|alle akzeptieren|cookies akzeptieren|accepter (les cookies|tout)|aceptar (todas|cookies)|accetta (tutti|i cookie)|accepteren";
+const PAYWALL_TEXT_PATTERN: &str = r"subscribe (now |today )?to (continue|keep) reading|already an? (subscriber|member)|(subscribe|sign in|log in|register) to (continue|read)|you('ve| have) reached (your|the) (limit|free article)|free articles? (left|remaining)|this (article|content) is (for|only for|exclusive to) subscribers|become a (subscriber|member) to";
+// larger blocks are more likely to be the content itself than an overlay
+const MAX_OVERLAY_TEXT_LEN: usize = 3000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OverlayInfo {
+  #[serde(rename = "consentWall")]
+  pub consent_wall: bool,
+  pub paywall: bool,
+  pub removed: usize,
+  #[serde(skip_serializing_if = "Vec::is_empty", default)]
+  pub vendors: Vec<String>,
+}
+
+impl OverlayInfo {
+  pub fn is_empty(&self) -> bool {
+    !self.consent_wall && !self.paywall && self.removed < 1
+  }
+}
+
+fn element_text(elem: &ElementRef) -> String {
+  collapse_whitespace(&elem.text().collect::<Vec<&str>>().join(" "))
+}
+
+fn is_root_element(elem: &ElementRef) -> bool {
+  matches!(elem.value().name(), "html" | "body" | "main" | "article")
+}
+
+// Elements positioned over the page or announced as dialogs
+fn is_floating(elem: &ElementRef, role_sel: &Selector) -> bool {
+  role_sel.matches(elem) || elem.value().attr("style").map(|style| style.replace(' ', "").to_lowercase().contains("position:fixed")).unwrap_or(false)
+}
+
+fn has_substantial_content(elem: &ElementRef, content_sel: &Selector, paragraph_sel: &Selector) -> bool {
+  elem.select(content_sel).next().is_some() || elem.select(paragraph_sel).count() > MAX_PARAGRAPHS
+}
+
+fn remove_elements(html_obj: &mut Html, ids: Vec<ego_tree::NodeId>) -> usize {
+  let id_set = ids.iter().copied().collect::<HashSet<_>>();
+  // only the outermost matches are counted, as their descendants go with them
+  let top_ids = id_set.iter().copied().filter(|id| {
+    html_obj.tree.get(*id).map(|node| node.parent().is_some() && !node.ancestors().any(|anc| id_set.contains(&anc.id()))).unwrap_or(false)
+  }).collect::<Vec<_>>();
+  for id in &top_ids {
+    html_obj.remove_from_parent(id);
+  }
+  top_ids.len()
+}
+
+// JSON-LD articles flagged as not accessible for free, as recommended for paywalled content
+pub fn has_paywalled_json_ld(metadata: &PageMetadata) -> bool {
+  metadata.json_ld.iter().any(|item| match item.get("isAccessibleForFree") {
+    Some(Value::Bool(free)) => !free,
+    Some(Value::String(free)) => free.eq_ignore_ascii_case("false"),
+    _ => false
+  })
+}
+
+// Remove consent banners, cookie notices and subscription overlays before the content is scored,
+// flagging consent walls and paywalls detected along the way
+pub fn remove_overlays(html_obj: &mut Html, metadata: &PageMetadata) -> OverlayInfo {
+  let mut info = OverlayInfo {
+    paywall: has_paywalled_json_ld(metadata),
+    ..Default::default()
+  };
+  let mut ids = vec![];
+  for (vendor, str_sel) in CMP_SELECTORS {
+    if let Ok(sel) = Selector::parse(str_sel) {
+      let matched = html_obj.select(&sel).map(|el| el.id()).collect::<Vec<_>>();
+      if !matched.is_empty() {
+        info.consent_wall = true;
+        info.vendors.push(vendor.to_string());
+        ids.extend(matched);
+      }
+    }
+  }
+  if let Ok(sel) = Selector::parse(PAYWALL_OVERLAY_SELECTOR) {
+    let matched = html_obj.select(&sel).map(|el| el.id()).collect::<Vec<_>>();
+    info.paywall = info.paywall || !matched.is_empty();
+    ids.extend(matched);
+  }
+  if let Ok(sel) = Selector::parse(PAYWALL_MARKER_SELECTOR) {
+    info.paywall = info.paywall || html_obj.select(&sel).next().is_some();
+  }
+  // generic dialogs and cookie notices are only removed if their text gives them away
+  let selectors = [DIALOG_SELECTOR, NOTICE_SELECTOR, DIALOG_ROLE_SELECTOR, CONTENT_SELECTOR, "p"].map(Selector::parse);
+  if let [Ok(dialog_sel), Ok(notice_sel), Ok(role_sel), Ok(content_sel), Ok(paragraph_sel)] = selectors {
+    for elem in html_obj.select(&dialog_sel).chain(html_obj.select(&notice_sel)).filter(|el| !is_root_element(el)) {
+      // class and id matches must float over the page or be too small to be the content
+      if !is_floating(&elem, &role_sel) && has_substantial_content(&elem, &content_sel, &paragraph_sel) {
+        continue;
+      }
+      let is_dialog = dialog_sel.matches(&elem);
+      if !is_dialog {
+        let classes = elem.value().classes().map(|c| c.to_string()).collect::<Vec<String>>();
+        if region_hint(elem.value().name(), elem.value().attr("role"), &classes, elem.value().id()) != Some(RegionKind::CookieBanner) {
+          continue;
+        }
+      }
+      let text = element_text(&elem);
+      if text.len() > MAX_OVERLAY_TEXT_LEN || ids.contains(&elem.id()) {
+        continue;
+      }
+      if text.pattern_match(CONSENT_TEXT_PATTERN, true) {
+        info.consent_wall = true;
+        ids.push(elem.id());
+      } else if is_dialog && text.pattern_match(PAYWALL_TEXT_PATTERN, true) {
+        info.paywall = true;
+        ids.push(elem.id());
+      }
+    }
+  }
+  info.removed = remove_elements(html_obj, ids);
+  info
+}
+
+#[cfg(test)]
+mod tests {
+  use scraper::Html;
+  use crate::metadata::PageMetadata;
+  use crate::overlays::{OverlayInfo, remove_overlays};
+
+  fn remove_from(html: &str) -> (OverlayInfo, String) {
+    let mut html = Html::parse_document(html);
+    let info = remove_overlays(&mut html, &PageMetadata::default());
+    (info, html.html())
+  }
+
+  #[test]
+  fn test_consent_vendor_is_removed() {
+    let (info, output) = remove_from(r#"<html><body><div id="onetrust-consent-sdk"><p>We value your privacy</p></div>
+      <article><p>Main story text about cookies and baking.</p></article></body></html>"#);
+    assert!(info.consent_wall);
+    assert_eq!(info.vendors, vec!["onetrust"]);
+    assert!(!output.contains("onetrust"));
+    assert!(output.contains("Main story text"));
+  }
+
+  #[test]
+  fn test_paywall_modal_is_removed() {
+    let (info, output) = remove_from(r#"<html><body><div class="site-modal" role="dialog"><p>Subscribe now to continue reading.</p></div>
+      <article><p>Main story text about cookies and baking.</p></article></body></html>"#);
+    assert!(info.paywall);
+    assert!(!output.contains("Subscribe now"));
+    assert!(output.contains("Main story text"));
+  }
+
+  #[test]
+  fn test_cookie_bar_is_removed_but_not_content() {
+    let (info, output) = remove_from(r#"<html><body><div id="onetrust-consent-sdk"><p>We value your privacy</p></div>
+      <div class="site-modal" role="dialog"><p>Subscribe now to continue reading.</p></div>
+      <div class="cookie-bar"><p>We use cookies to improve your experience. <button>Accept all cookies</button></p></div>
+      <article><p>Main story text about cookies and baking.</p></article></body></html>"#);
+    assert!(!output.contains("We use cookies"));
+    assert!(output.contains("Main story text"));
+    assert_eq!(info.removed, 3);
+  }
+
+  #[test]
+  fn test_wrapper_with_page_content_is_kept() {
+    let (info, output) = remove_from(r#"<html><body><div id="onetrust-consent-sdk"><div id="onetrust-banner-sdk">Cookies</div></div>
+      <div class="page-overlay-wrapper"><h1>Cookie policy</h1><p>We use cookies for analytics.</p><p>Accept all cookies to continue.</p></div></body></html>"#);
+    assert_eq!(info.removed, 1);
+    assert!(output.contains("Cookie policy"));
+  }
+}
@@ -3,13 +3,30 @@ use core::time::Duration as StdDuration;
 use scraper::{Html, Selector, ElementRef};
 use html5ever::tree_builder::TreeSink;
 use serde_with::skip_serializing_none;
-use crate::cache::{FlatPage, redis_get_page, redis_set_page};
-use crate::cleantext::{clean_raw_html, strip_literal_tags};
+use crate::cache::{FlatPage, HTML_CONTENT_TYPE, redis_get_page, redis_store_page};
+use crate::cleantext::clean_raw_html;
 use crate::expand_path::expand_css_path;
 use simple_string_patterns::*;
 use string_patterns::*;
 use crate::stats::*;
-use crate::params::{TargetConfig,TargetKind};
+use crate::params::{TargetConfig,TargetKind,ExtractMode,OutputFormat,PostParams,QueryParams,LinkFilter};
+use crate::plaintext::render_plain_text;
+use crate::outline::{OutlineSection, build_outline, clear_offsets, shift_offsets};
+use crate::tables::{TableData, extract_tables};
+use crate::media::{MediaItem, EMBEDDED_MEDIA_SELECTOR, extract_media};
+use crate::regions::{RegionKind, classify_regions, exclude_regions};
+use crate::overlays::{OverlayInfo, remove_overlays};
+use crate::pagination::{PaginationInfo, MAX_STITCHED_PAGES, detect_pagination, page_boundary};
+use crate::uris::document_base_uri;
+use crate::links::extract_links;
+use crate::navigation::{NavItem, Breadcrumbs, extract_navigation, extract_breadcrumbs};
+use crate::metadata::PageMetadata;
+use crate::byline::ArticleInfo;
+use crate::language::{LanguageInfo, extract_declared_lang, primary_subtag};
+use crate::metrics::ReadingMetrics;
+use crate::pdf::{PDF_CONTENT_TYPE, PdfDocument, PdfMetadata, extract_pdf, is_pdf};
+use crate::structured::{ContentKind, StructuredSource};
+use crate::xpath::HtmlXPathDocument;
 use base64::{Engine as _, engine::general_purpose};
 use reqwest::{Client, Error};
 use select::document::Document;
@@ -72,6 +89,28 @@ pub fn extract_html_as_vec(selector_str: &str, html_obj: &Html) -> Vec<String> {
     elements
   }
 
+// The requested attribute of an element, or else the element as text, inner or outer HTML
+pub fn render_element(el: ElementRef, attr: Option<&str>, mode: ExtractMode) -> Option<String> {
+  match attr {
+    Some(name) => el.value().attr(name).map(|value| value.trim().to_owned()),
+    None => Some(match mode {
+      // line breaks, paragraphs and list items stay apart as in the plain text rendering,
+      // without a bullet when the matched element is itself a list item
+      ExtractMode::Text => render_plain_text(&el.inner_html(), None).text,
+      ExtractMode::InnerHtml => el.inner_html(),
+      ExtractMode::OuterHtml => el.html(),
+    })
+  }
+}
+
+// Matched elements rendered for an item, skipping those without the requested attribute
+pub fn extract_values_as_vec(selector_str: &str, html_obj: &Html, attr: Option<&str>, mode: ExtractMode) -> Vec<String> {
+  match Selector::parse(selector_str) {
+    Ok(selector) => html_obj.select(&selector).filter_map(|el| render_element(el, attr, mode)).collect(),
+    Err(_) => vec![]
+  }
+}
+
 pub fn extract_best_html(selector_str: &str, html_obj: &Html) -> String {
   let inner = extract_html_as_vec(selector_str, html_obj);
   if inner.len() > 0 {
@@ -85,19 +124,75 @@ pub fn to_page_key(uri: &str) -> String {
   general_purpose::STANDARD_NO_PAD.encode(uri)
 }
 
+// Documents that must be read as bytes and sniffed rather than decoded as text,
+// as PDFs are also served as application/x-pdf, application/x-download and the like
+fn is_binary_response(uri: &str, content_type: &str) -> bool {
+  let path = uri.split(['?', '#']).next().unwrap_or("").to_lowercase();
+  !is_text_content_type(content_type) || path.ends_with(".pdf")
+}
+
+fn is_text_content_type(content_type: &str) -> bool {
+  content_type.starts_with("text/") || ["html", "json", "xml", "javascript"].iter().any(|part| content_type.contains(part))
+}
+
+// PDFs are detected by content type or magic bytes and cached as their extracted text.
+// Other binary documents such as images and archives yield an empty page, which is not cached
+async fn page_from_bytes(uri: &str, bytes: Vec<u8>, content_type: &str) -> FlatPage {
+  if is_pdf(&bytes, Some(content_type)) {
+    if let Ok(Some(doc)) = tokio::task::spawn_blocking(move || extract_pdf(&bytes)).await {
+      if let Ok(content) = serde_json::to_string(&doc) {
+        return FlatPage::new(uri, &content, false).with_content_type(PDF_CONTENT_TYPE);
+      }
+    }
+    FlatPage::empty()
+  } else if is_text_content_type(content_type) {
+    FlatPage::new(uri, &String::from_utf8_lossy(&bytes), false).with_content_type(content_type)
+  } else {
+    FlatPage::empty()
+  }
+}
+
 pub async fn get_page(uri: &str) -> Result<FlatPage, Error> {
   let client = get_client();
   let result = client.get(uri).timeout(StdDuration::from_secs(MAX_TIMEOUT_SECS)).send().await;
   match result {
-     Ok(req) => if let Ok(html_raw) = req.text().await {
-          Ok(FlatPage::new(uri, &html_raw, false))
+     Ok(req) => {
+      let content_type = req.headers().get(reqwest::header::CONTENT_TYPE).and_then(|ct| ct.to_str().ok()).unwrap_or(HTML_CONTENT_TYPE).to_lowercase();
+      if is_binary_response(uri, &content_type) {
+        if let Ok(bytes) = req.bytes().await {
+          Ok(page_from_bytes(uri, bytes.to_vec(), &content_type).await)
+        } else {
+          Ok(FlatPage::empty())
+        }
+      } else if let Ok(html_raw) = req.text().await {
+          Ok(FlatPage::new(uri, &html_raw, false).with_content_type(&content_type))
       } else {
           Ok(FlatPage::empty())
-      },
+      }
+     },
       Err(error) => Err(error)
   }
 }
 
+// Raw body and content type of a successful response, for compressed and binary documents.
+// Bodies over `max_bytes` are rejected, by their declared length or once that many bytes have arrived
+pub async fn get_page_bytes(uri: &str, max_bytes: usize) -> Option<(Vec<u8>, Option<String>)> {
+  let client = get_client();
+  let mut response = client.get(uri).timeout(StdDuration::from_secs(MAX_TIMEOUT_SECS)).send().await.ok()?;
+  if !response.status().is_success() || response.content_length().map(|len| len > max_bytes as u64).unwrap_or(false) {
+    return None;
+  }
+  let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|ct| ct.to_str().ok()).map(|ct| ct.to_lowercase());
+  let mut bytes: Vec<u8> = vec![];
+  while let Some(chunk) = response.chunk().await.ok()? {
+    if bytes.len() + chunk.len() > max_bytes {
+      return None;
+    }
+    bytes.extend_from_slice(&chunk);
+  }
+  Some((bytes, content_type))
+}
+
 pub async fn fetch_page(uri: &str, skip_cache: bool) -> Option<FlatPage> {
   let key = to_page_key(uri);
   let age_mins = if skip_cache {
@@ -110,7 +205,10 @@ pub async fn fetch_page(uri: &str, skip_cache: bool) -> Option<FlatPage> {
       Some(pd)
   } else {
       if let Ok(pd ) = get_page(&uri).await {
-          redis_set_page(&key, &pd.uri, &pd.content, false);
+          // failed downloads and extractions are retried rather than cached
+          if !pd.content.is_empty() {
+              redis_store_page(&key, &pd);
+          }
           Some(pd)
       } else {
           None
@@ -164,6 +262,53 @@ impl PageResultSet {
     pub fn add_related(&mut self, result_set: PageResultSet) {
         self.related.push(result_set);
     }
+
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
+    pub fn best_text(&self) -> Option<String> {
+        self.content.as_ref().and_then(|pi| pi.best_text.clone())
+    }
+
+    pub fn next_page_uri(&self) -> Option<String> {
+        self.content.as_ref().and_then(|pi| pi.pagination.as_ref()).and_then(|pg| pg.next.clone())
+    }
+
+    // Stitch the content of the next page onto this result, returning false if it has no content
+    pub fn append_page(&mut self, result_set: PageResultSet, page: usize, format: OutputFormat) -> bool {
+        match (self.content.as_mut(), result_set.content) {
+            (Some(pi), Some(next)) => {
+                pi.append_page(next, page, format);
+                true
+            },
+            _ => false
+        }
+    }
+
+    pub fn set_page_uris(&mut self, uris: Vec<String>) {
+        if let Some(pi) = self.content.as_mut() {
+            pi.page_uris = uris;
+        }
+    }
+}
+
+// Cast matched text to the JSON type implied by the target kind
+pub fn to_typed_value(text: &str, kind: Option<TargetKind>) -> Value {
+  match kind {
+    Some(TargetKind::Boolean) => json!(text.is_truthy()),
+    Some(TargetKind::Integer) => if let Some(n) = text.to_first_number::<i64>() {
+      json!(n)
+    } else {
+      json!(text)
+    },
+    Some(TargetKind::Float) => if let Some(n) = text.to_first_number::<f64>() {
+      json!(n)
+    } else {
+      json!(text)
+    },
+    _ => Value::String(text.to_string()),
+  }
 }
 
 #[skip_serializing_none]
@@ -194,32 +339,12 @@ impl Snippet {
     }
 
     pub fn new_item(source_texts: &[String], path: &str, key_str: &str, multiple: bool, kind: Option<TargetKind>) -> Self {
-        
-        let data_type = if let Some(tk) = kind {
-          match tk {
-            TargetKind::Boolean => 1,
-            TargetKind::Integer => 2,
-            TargetKind::Float => 3,
-            _ => 0
-          }
-        } else {
-          0
-        };
-        let matched_items =  source_texts.to_vec()
-          .into_iter().map(|t| match data_type {
-              1 => json!(t.is_truthy()),
-              2 => if let Some(n) = t.to_first_number::<i64>() {
-                json!(n)
-              } else {
-                json!(t)
-              },
-              3 => if let Some(n) = t.to_first_number::<f64>() {
-                json!(n)
-              } else {
-                json!(t)
-              },
-              _ => Value::String(t),
-          }).collect::<Vec<Value>>();
+        let matched_items =  source_texts.iter().map(|t| to_typed_value(t, kind)).collect::<Vec<Value>>();
+        Snippet::from_values(matched_items, path, key_str, multiple, kind)
+    }
+
+    // Values already typed, such as JSON matched by a JSONPath
+    pub fn from_values(matched_items: Vec<Value>, path: &str, key_str: &str, multiple: bool, kind: Option<TargetKind>) -> Self {
         let matches = if multiple {
           matched_items.clone()
         } else {
@@ -283,6 +408,25 @@ pub struct PageInfo {
   pub best_text: Option<String>,
   #[serde(rename = "compactTextLength")]
   pub compact_text_len: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub metrics: Option<ReadingMetrics>,
+  #[serde(skip_serializing_if = "Vec::is_empty", default)]
+  pub outline: Vec<OutlineSection>,
+  #[serde(skip_serializing_if = "Vec::is_empty", default)]
+  pub media: Vec<MediaItem>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub overlays: Option<OverlayInfo>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub pagination: Option<PaginationInfo>,
+  #[serde(rename = "pageUris", skip_serializing_if = "Vec::is_empty", default)]
+  pub page_uris: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub document: Option<PdfMetadata>,
+  #[serde(rename = "contentType", skip_serializing_if = "Option::is_none", default)]
+  pub content_type: Option<String>,
+  // parsed body of JSON responses
+  #[serde(skip_serializing_if = "Option::is_none", default)]
+  pub data: Option<Value>,
 }
 
 impl PageInfo {
@@ -298,8 +442,78 @@ impl PageInfo {
           compact_len,
           cached,
           best_text,
-          compact_text_len
+          compact_text_len,
+          metrics: None,
+          outline: vec![],
+          media: vec![],
+          overlays: None,
+          pagination: None,
+          page_uris: vec![],
+          document: None,
+          content_type: None,
+          data: None
+      }
+  }
+
+  pub fn set_metrics(&mut self, metrics: ReadingMetrics) {
+      self.metrics = Some(metrics);
+  }
+
+  pub fn set_outline(&mut self, outline: Vec<OutlineSection>) {
+      self.outline = outline;
+  }
+
+  pub fn set_media(&mut self, media: Vec<MediaItem>) {
+      self.media = media;
+  }
+
+  pub fn set_overlays(&mut self, overlays: OverlayInfo) {
+      if !overlays.is_empty() {
+          self.overlays = Some(overlays);
+      }
+  }
+
+  pub fn set_pagination(&mut self, pagination: Option<PaginationInfo>) {
+      self.pagination = pagination;
+  }
+
+  pub fn set_document(&mut self, document: PdfMetadata) {
+      self.document = Some(document);
+  }
+
+  pub fn set_content_type(&mut self, content_type: &str) {
+      self.content_type = Some(content_type.to_owned());
+  }
+
+  pub fn set_data(&mut self, data: Value) {
+      self.data = Some(data);
+  }
+
+  // Append the main content of a following page after a boundary marker,
+  // carrying its outline and reading metrics over to the stitched text
+  pub fn append_page(&mut self, next: PageInfo, page: usize, format: OutputFormat) {
+      let next_text = next.best_text.unwrap_or_default();
+      if !next_text.is_empty() {
+          let prev_text = self.best_text.clone().unwrap_or_default();
+          let boundary = page_boundary(page, format);
+          let mut next_outline = next.outline;
+          if format == OutputFormat::Text {
+              shift_offsets(&mut next_outline, prev_text.chars().count() + boundary.chars().count());
+          }
+          self.outline.extend(next_outline);
+          let text = [prev_text, boundary, next_text].concat();
+          self.compact_text_len = text.len();
+          self.best_text = Some(text);
+      }
+      if let (Some(metrics), Some(next_metrics)) = (self.metrics.as_mut(), next.metrics.as_ref()) {
+          metrics.append(next_metrics);
       }
+      self.source_len += next.source_len;
+      self.stripped_len += next.stripped_len;
+      self.compact_len += next.compact_len;
+      self.cached = self.cached && next.cached;
+      self.media.extend(next.media);
+      self.pagination = next.pagination;
   }/* 
 
   pub fn empty() -> Self {
@@ -347,12 +561,75 @@ impl ShowMode {
     }
 }
 
+// Output options applied to the extracted main content
+#[derive(Debug, Clone, Default)]
+pub struct ContentOptions {
+    pub format: OutputFormat,
+    pub wrap: Option<usize>,
+    pub metrics: bool,
+    pub outline: bool,
+    pub keep_media: bool,
+    pub media: bool,
+    pub exclude: Vec<RegionKind>,
+    pub keep_overlays: bool,
+    pub pages: usize,
+    pub subdomains: bool,
+}
+
+impl ContentOptions {
+    pub fn from_params(params: &PostParams) -> Self {
+        ContentOptions {
+            format: params.format.unwrap_or_default(),
+            wrap: params.wrap,
+            metrics: params.metrics.unwrap_or(false),
+            outline: params.outline.unwrap_or(false),
+            keep_media: params.keep_media.unwrap_or(false),
+            media: params.media.unwrap_or(false),
+            exclude: params.exclude.clone().unwrap_or_default(),
+            keep_overlays: params.keep_overlays.unwrap_or(false),
+            pages: (params.pages.unwrap_or(1) as usize).clamp(1, MAX_STITCHED_PAGES),
+            subdomains: params.subdomains.unwrap_or(true),
+        }
+    }
+
+    pub fn from_query(params: &QueryParams) -> Self {
+        ContentOptions {
+            format: params.format.unwrap_or_default(),
+            wrap: params.wrap,
+            metrics: params.metrics.unwrap_or(0) > 0,
+            outline: params.outline.unwrap_or(0) > 0,
+            keep_media: false,
+            media: false,
+            exclude: vec![],
+            keep_overlays: false,
+            pages: 1,
+            subdomains: params.subdomains.unwrap_or(1) > 0,
+        }
+    }
+
+    pub fn as_text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LinkItem {
-    uri: String,
-    title: String,
-    summary: String,
-    local: bool
+    pub uri: String,
+    pub title: String,
+    pub summary: String,
+    pub local: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub rel: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hreflang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<RegionKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    #[serde(rename = "fileType", skip_serializing_if = "Option::is_none")]
+    pub file_type: Option<String>,
 }
 
 impl LinkItem {
@@ -361,23 +638,36 @@ impl LinkItem {
             uri: uri.to_owned(),
             title: title.to_owned(),
             summary: summary.to_owned(),
-            local
+            local,
+            rel: vec![],
+            target: None,
+            hreflang: None,
+            region: None,
+            heading: None,
+            file_type: None,
         }
     }
 }
 
-fn strip_extra_tags(html_obj: &mut Html) {
-  if let Ok(sel) = Selector::parse("img,video,audio,object,figure,iframe,svg,path") {
+fn strip_extra_tags(html_obj: &mut Html, keep_media: bool) {
+  let extra_tags = if keep_media {
+    "object,svg,path"
+  } else {
+    "img,video,audio,object,figure,iframe,svg,path"
+  };
+  if let Ok(sel) = Selector::parse(extra_tags) {
     let ids = html_obj.select(&sel).into_iter().map(|el| el.id()).collect::<Vec<_>>();
     for id in ids {
         html_obj.remove_from_parent(&id);
     }
   }
-  // remove empty tags
+  // remove empty tags, unless they wrap media that should be kept
+  let media_sel = Selector::parse(EMBEDDED_MEDIA_SELECTOR).ok().filter(|_| keep_media);
   if let Ok(sel) = Selector::parse("div,span,a") {
     for elem in html_obj.clone().select(&sel) {
         let inner_text_len = extract_inner_text_length(&elem);
-        if inner_text_len < 1 {
+        let has_media = media_sel.as_ref().map(|ms| elem.select(ms).next().is_some()).unwrap_or(false);
+        if inner_text_len < 1 && !has_media {
             html_obj.remove_from_parent(&elem.id());
         }
     }
@@ -385,12 +675,16 @@ fn strip_extra_tags(html_obj: &mut Html) {
 }
 
 // Build a PageInfo object with the best matched HTML text
-pub fn build_page_content_data(uri: &str, html_raw: &str, mode: ShowMode, strip_extra: bool, target: Option<String>, show_raw: bool, cached: bool) -> PageResultSet {
+#[allow(clippy::too_many_arguments)]
+pub fn build_page_content_data(uri: &str, html_raw: &str, mode: ShowMode, strip_extra: bool, target: Option<String>, show_raw: bool, cached: bool, options: &ContentOptions) -> PageResultSet {
   let has_target = target.is_some();
   let show_elements = mode.show_elements();
   let show_links = mode.show_links();
 
   let html = clean_raw_html(html_raw);
+  // scripts and link tags are stripped below, so metadata is read from the full source
+  let source_doc = Document::from(html.as_str());
+  let metadata = PageMetadata::new(&source_doc, uri);
 
   let mut html_obj = Html::parse_fragment(html.as_str());
   /*  let mut fragment = Html::parse_fragment(&html);
@@ -402,6 +696,9 @@ pub fn build_page_content_data(uri: &str, html_raw: &str, mode: ShowMode, strip_
   let mut compact_html = "".to_string();
   let mut compact_text_len: usize = 0;
   let mut best_text = "".to_string();
+  let mut media_source: Option<Html> = None;
+  let mut content_selector = "html".to_string();
+  let mut overlays = OverlayInfo::default();
   // println!("start post processing");
 
   
@@ -410,24 +707,67 @@ pub fn build_page_content_data(uri: &str, html_raw: &str, mode: ShowMode, strip_
       for id in ids {
           html_obj.remove_from_parent(&id);
       }
+      if !options.keep_overlays {
+        overlays = remove_overlays(&mut html_obj, &metadata);
+      }
       stripped_html = html_obj.html();
       stripped_len = stripped_html.len();
+      if options.media {
+        media_source = Some(html_obj.clone());
+      }
       if strip_extra {
-        strip_extra_tags(&mut html_obj);
+        strip_extra_tags(&mut html_obj, options.keep_media);
       }
       if !has_target {
           compact_html = html_obj.html();
           compact_text_len = extract_inner_text_length(&html_obj.root_element());
       }
   }
+  let stats_doc = if show_elements || !has_target {
+      let ref_html = if has_target { stripped_html.as_str() } else { compact_html.as_str() };
+      Some(Document::from(ref_html))
+  } else {
+      None
+  };
+  let mut p_stats = stats_doc.as_ref().map(|doc| {
+      let mut ps = PageStats::new(doc, &uri, show_links, options.subdomains);
+      ps.set_article(ArticleInfo::new(&source_doc, &metadata));
+      ps.set_metadata(metadata.clone());
+      ps
+  });
+  // regions are only listed with full stats or needed for exclusion, and are classified once
+  // from the html the best text is read from, which the stats document mirrors without a target
+  let regions = if show_links || !options.exclude.is_empty() {
+      match stats_doc.as_ref().filter(|_| !has_target) {
+          Some(doc) => classify_regions(doc),
+          None => classify_regions(&Document::from(html_obj.html().as_str())),
+      }
+  } else {
+      vec![]
+  };
+  let best_selector = match &target {
+      Some(tg) => Some(tg.to_head_tail("/").1),
+      None => p_stats.as_ref().and_then(|ps| ps.best_content_match()).map(|el| el.selector()),
+  };
+  // excluded regions are only removed from the html the best text is read from
+  let content_obj = if options.exclude.is_empty() {
+      None
+  } else {
+      exclude_regions(&html_obj, &regions, &options.exclude, best_selector.as_deref())
+  };
+  let content_ref = content_obj.as_ref().unwrap_or(&html_obj);
+  if let Some(ps) = p_stats.as_mut() {
+      ps.set_regions(regions);
+  }
 
   if let Some(tg) = target {
     let (header_target, content_target) = tg.to_head_tail("/");
     let has_header_target = header_target.len() > 1;
     
-    best_text = extract_best_html(&content_target, &html_obj);
+    best_text = extract_best_html(&content_target, content_ref);
+    content_selector = content_target.clone();
     if has_header_target {
-      let header = extract_best_html(&header_target, &html_obj);
+      let header = extract_best_html(&header_target, content_ref);
       if header.len() > 1 {
         best_text = [r#"<div class="content-wrapper">"#, &header, &best_text,"</div>"].concat();
       }
@@ -443,24 +783,49 @@ pub fn build_page_content_data(uri: &str, html_raw: &str, mode: ShowMode, strip_
   } else {
       compact_html.len()
   };
-  let p_stats = if show_elements || !has_target {
-      let ref_html = if has_target { stripped_html.as_str() } else { compact_html.as_str() };
-      let doc = Document::from(ref_html);
-      let ps = PageStats::new(&doc, &uri, show_links);
-      Some(ps)
+  if let Some(str_sel) = best_selector.filter(|_| !has_target) {
+      best_text = extract_best_html(&str_sel, content_ref);
+      content_selector = str_sel;
+  }
+  // language is only reported with full stats, as on /page-stats, but also picks the readability formula
+  let show_language = show_links && p_stats.is_some();
+  let needs_language = show_language || options.metrics;
+  // the plain text render is skipped when neither the output nor any analysis needs it
+  let rendered = (options.as_text() || options.outline || needs_language).then(|| render_plain_text(&best_text, options.wrap));
+  // offsets in the outline refer to the plain text as returned with format=text
+  let mut outline = match rendered.as_ref().filter(|_| options.outline) {
+      Some(rt) => build_outline(rt),
+      None => vec![]
+  };
+  if !options.as_text() {
+      clear_offsets(&mut outline);
+  }
+  let plain_text = rendered.map(|rt| rt.text).unwrap_or_default();
+  let language = needs_language.then(|| LanguageInfo::new(extract_declared_lang(&source_doc), &plain_text));
+  let metrics = if options.metrics {
+      // declared region subtags such as en-GB would otherwise never match a readability formula
+      let lang = language.as_ref().and_then(|lg| lg.detected.clone().or(lg.declared.as_deref().map(primary_subtag)));
+      Some(ReadingMetrics::new(&plain_text, lang.as_deref()))
   } else {
       None
   };
-  if !has_target {
-      if let Some(ps) = p_stats.clone() {
-          if let Some(best_text_element) = ps.best_content_match() {
-              let str_sel = best_text_element.selector();
-              best_text = extract_best_html(&str_sel, &html_obj);
-          }
-      }
+  if let (Some(ps), Some(language)) = (p_stats.as_mut(), language.filter(|_| show_language)) {
+      ps.set_language(language);
+  }
+  if options.as_text() {
+      best_text = plain_text;
   }
   let compact_text_len = best_text.len();
-  let pi = PageInfo::new(source_len, stripped_len, compact_len, cached, &best_text, compact_text_len);
+  let mut pi = PageInfo::new(source_len, stripped_len, compact_len, cached, &best_text, compact_text_len);
+  if let Some(rm) = metrics {
+      pi.set_metrics(rm);
+  }
+  pi.set_outline(outline);
+  pi.set_overlays(overlays);
+  pi.set_pagination(detect_pagination(&source_doc, uri));
+  if let Some(source) = media_source {
+      pi.set_media(extract_media(&source, &content_selector, uri));
+  }
   let raw = if show_raw { Some(html) } else { None };
   let overview = if let Some(ps) = p_stats.clone() {
       Some(ps.to_result(show_links))
@@ -470,6 +835,78 @@ pub fn build_page_content_data(uri: &str, html_raw: &str, mode: ShowMode, strip_
   PageResultSet::new(overview, Some(pi), raw)
 }
 
+// PDF text in the same PageInfo shape, with the document metadata in place of page stats
+pub fn build_pdf_content_data(pd: &FlatPage, options: &ContentOptions) -> PageResultSet {
+  let Ok(doc) = serde_json::from_str::<PdfDocument>(&pd.content) else {
+    return PageResultSet::empty();
+  };
+  let plain_text = doc.to_text(OutputFormat::Text);
+  let best_text = doc.to_text(options.format);
+  let mut pi = PageInfo::new(pd.content.len(), plain_text.len(), best_text.len(), pd.cached, &best_text, best_text.len());
+  if options.metrics {
+      pi.set_metrics(ReadingMetrics::new(&plain_text, None));
+  }
+  pi.set_content_type(PDF_CONTENT_TYPE);
+  pi.set_document(doc.metadata);
+  PageResultSet::new(None, Some(pi), None)
+}
+
+// JSON is returned parsed, while XML and plain text pass through without HTML heuristics
+pub fn build_structured_content_data(pd: &FlatPage, kind: ContentKind, options: &ContentOptions) -> PageResultSet {
+  let data = if kind == ContentKind::Json { serde_json::from_str::<Value>(&pd.content).ok() } else { None };
+  let best_text = if data.is_some() { "" } else { pd.content.as_str() };
+  let mut pi = PageInfo::new(pd.content.len(), pd.content.len(), best_text.len(), pd.cached, best_text, best_text.len());
+  if options.metrics && kind == ContentKind::Text {
+      pi.set_metrics(ReadingMetrics::new(best_text, None));
+  }
+  pi.set_content_type(&pd.content_type);
+  if let Some(value) = data {
+      pi.set_data(value);
+  }
+  PageResultSet::new(None, Some(pi), None)
+}
+
+// Evaluate targets and items as JSONPath, XPath or line patterns depending on the content type
+pub fn build_structured_content_items(source: &StructuredSource, targets: &[String], items: &[TargetConfig], cached: bool) -> ContentResultSet {
+  let mut snippets: Vec<Snippet> = targets.iter().map(|path| Snippet::new(&source.select_text(path), path)).collect();
+  for item in items {
+    let key_str = item.key.clone().unwrap_or_default();
+    let multiple = item.multiple.unwrap_or(false);
+    let paths = match (&item.path, &item.paths, &item.xpath, source) {
+      (Some(p), _, _, _) => vec![p.clone()],
+      (None, Some(ps), _, _) => ps.clone(),
+      // paths into XML bodies are already XPath, while JSON is only queried with JSONPath
+      (None, None, Some(xp), StructuredSource::Xml(_)) => vec![xp.clone()],
+      // plain text items may have only a pattern
+      _ => vec!["".to_string()],
+    };
+    for path in paths {
+      let matched_items = source.select_item(&path, item);
+      if !matched_items.is_empty() {
+        snippets.push(Snippet::from_values(matched_items, &path, &key_str, multiple, item.kind));
+      }
+    }
+  }
+  ContentResultSet::new(None, snippets, cached)
+}
+
+// Content items for a fetched page of any supported content type
+pub fn build_content_items(uri: &str, pd: &FlatPage, targets: &[String], items: &[TargetConfig]) -> ContentResultSet {
+  let source = match ContentKind::detect(&pd.content_type, &pd.content) {
+    ContentKind::Html => None,
+    // PDF text is matched line by line like plain text
+    ContentKind::Pdf => match serde_json::from_str::<PdfDocument>(&pd.content) {
+      Ok(doc) => StructuredSource::new(ContentKind::Text, &doc.to_text(OutputFormat::Text)),
+      Err(_) => Some(StructuredSource::Text("".to_string()))
+    },
+    kind => StructuredSource::new(kind, &pd.content)
+  };
+  match source {
+    Some(source) => build_structured_content_items(&source, targets, items, pd.cached),
+    None => build_page_content_items(uri, &pd.content, targets, items, pd.cached)
+  }
+}
+
 // Build content results with multiple targets
 pub fn build_page_content_items(uri: &str, html_raw: &str, targets: &[String], items: &[TargetConfig], cached: bool) -> ContentResultSet {
   let num_targets = targets.len();
@@ -505,11 +942,14 @@ pub fn build_page_content_items(uri: &str, html_raw: &str, targets: &[String], i
 
   if has_items {
     let strip_rgx = build_regex(r#"</?\w+[^>]*?>"#, true).unwrap();
+    let mut xpath_doc: Option<HtmlXPathDocument> = None;
     for item in items.to_vec() {
       let key_str = item.key.unwrap_or("".to_string());
       let multiple = item.multiple.unwrap_or(false);
       let kind = item.kind;
       let plain = item.plain.unwrap_or(false);
+      let attr = item.attr;
+      let mode = item.mode.unwrap_or_default();
       let re_opt = if let Some(pat) = item.pattern {
         if let Ok(rgx) = build_regex(&pat, true) {
           Some(rgx)
@@ -524,9 +964,18 @@ pub fn build_page_content_items(uri: &str, html_raw: &str, targets: &[String], i
       } else {
         item.paths.unwrap_or(vec![])
       };
-      for css_path in paths {
-        let path = expand_css_path(&css_path);
-        let txts = extract_html_as_vec(&path, &html_obj);
+      let mut selections = paths.iter().map(|css_path| {
+        let path = expand_css_path(css_path);
+        let txts = extract_values_as_vec(&path, &html_obj, attr.as_deref(), mode);
+        (path, txts)
+      }).collect::<Vec<(String, Vec<String>)>>();
+      if let Some(xpath) = item.xpath {
+        // the stripped html is parsed as a whole document so absolute paths from /html resolve
+        let doc_obj = xpath_doc.get_or_insert_with(|| HtmlXPathDocument::new(Html::parse_document(stripped_html.as_str())));
+        let txts = doc_obj.extract(&xpath, |el| render_element(el, attr.as_deref(), mode));
+        selections.push((xpath, txts));
+      }
+      for (path, txts) in selections {
           if txts.len() > 0 {
             if let Some(re) = re_opt.clone() {
               let plain_txts = txts.iter().map(|txt| strip_rgx.replace_all(txt, "").to_string()).collect::<Vec<String>>();
@@ -559,7 +1008,11 @@ pub fn build_page_content_items(uri: &str, html_raw: &str, targets: &[String], i
 
   let p_stats = if stripped_html.len() > 0 {
       let doc = Document::from(stripped_html.as_str());
-      let ps = PageStats::new(&doc, &uri, false);
+      let mut ps = PageStats::new(&doc, &uri, false, true);
+      let source_doc = Document::from(html.as_str());
+      let metadata = PageMetadata::new(&source_doc, uri);
+      ps.set_article(ArticleInfo::new(&source_doc, &metadata));
+      ps.set_metadata(metadata);
       Some(ps)
   } else {
       None
@@ -573,48 +1026,129 @@ pub fn build_page_content_items(uri: &str, html_raw: &str, targets: &[String], i
   ContentResultSet::new(overview, snippets, cached)
 }
 
-pub async fn fetch_page_data(uri: &str, mode: ShowMode, strip_extra: bool, target: Option<String>, show_raw: bool, skip_cache: bool) -> PageResultSet {
+#[derive(Debug, Serialize, Clone)]
+pub struct TableResultSet {
+    tables: Vec<TableData>,
+    cached: bool,
+    valid: bool
+}
+
+// Build structured tables, either all tables or those matched by the target selector
+pub fn build_page_tables(html_raw: &str, target: Option<String>, with_csv: bool, cached: bool) -> TableResultSet {
+  let html = clean_raw_html(html_raw);
+  let html_obj = Html::parse_fragment(html.as_str());
+  let path = target.map(|tg| expand_css_path(&tg));
+  let tables = extract_tables(&html_obj, path.as_deref(), with_csv);
+  let valid = !tables.is_empty();
+  TableResultSet {
+    tables,
+    cached,
+    valid
+  }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NavigationResultSet {
+    navigation: Vec<NavItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selector: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breadcrumbs: Option<Breadcrumbs>,
+    cached: bool,
+    valid: bool
+}
+
+// Build the primary navigation tree from the ranked menu elements plus any breadcrumbs
+pub fn build_page_navigation(uri: &str, html_raw: &str, cached: bool) -> NavigationResultSet {
+  let html = clean_raw_html(html_raw);
+  let doc = Document::from(html.as_str());
+  let stats = PageStats::new(&doc, uri, false, true);
+  let metadata = PageMetadata::new(&doc, uri);
+  let html_obj = Html::parse_document(html.as_str());
+  let base_uri = document_base_uri(&doc, uri);
+  let (navigation, selector) = extract_navigation(&html_obj, &stats.top_menu_elements(), &base_uri);
+  let breadcrumbs = extract_breadcrumbs(&html_obj, &metadata, &base_uri);
+  let valid = !navigation.is_empty() || breadcrumbs.is_some();
+  NavigationResultSet {
+    navigation,
+    selector,
+    breadcrumbs,
+    cached,
+    valid
+  }
+}
+
+// Only HTML pages are parsed for links, tables and navigation
+pub fn is_html_page(pd: &FlatPage) -> bool {
+  ContentKind::detect(&pd.content_type, &pd.content) == ContentKind::Html
+}
+
+// Page data for a fetched page of any supported content type
+pub fn build_content_data(uri: &str, pd: &FlatPage, mode: ShowMode, strip_extra: bool, target: Option<String>, show_raw: bool, options: &ContentOptions) -> PageResultSet {
+  match ContentKind::detect(&pd.content_type, &pd.content) {
+    ContentKind::Pdf => build_pdf_content_data(pd, options),
+    ContentKind::Html => build_page_content_data(uri, &pd.content, mode, strip_extra, target, show_raw, pd.cached, options),
+    kind => build_structured_content_data(pd, kind, options)
+  }
+}
+
+pub async fn fetch_page_data(uri: &str, mode: ShowMode, strip_extra: bool, target: Option<String>, show_raw: bool, skip_cache: bool, options: &ContentOptions) -> PageResultSet {
   //let mut node_items: Vec<PageElement> = vec![];
   if let Some(pd) = fetch_page(uri, skip_cache).await {
-    build_page_content_data(uri, &pd.content, mode, strip_extra, target, show_raw, pd.cached)
+    build_content_data(uri, &pd, mode, strip_extra, target, show_raw, options)
   } else {
     PageResultSet::empty()
   }
 }
 
-fn is_javascript_link(title: &str, uri: &str) -> bool {
-    let patterns = [r"\{", r"\}"];
-    let title_suspect = title.to_owned().pattern_match_all(&patterns, true);
-    if !title_suspect {
-        uri.to_owned().pattern_match_all(&patterns, true)
+pub async fn fetch_page_links(uri: &str, include_subdomains: bool, filter: Option<&LinkFilter>) -> Vec<LinkItem> {
+    if let Some(pd) = fetch_page(uri, false).await.filter(is_html_page) {
+        let html = clean_raw_html(&pd.content);
+        let html_obj = Html::parse_fragment(html.as_str());
+        extract_links(&html_obj, uri, include_subdomains, filter)
     } else {
-        title_suspect
+        vec![]
     }
 }
 
-pub async fn fetch_page_links(uri: &str) -> Vec<LinkItem> {
-    let mut links: Vec<LinkItem> = Vec::new();
-    //let mut node_items: Vec<PageElement> = vec![];
-    if let Some(pd) = fetch_page(uri, false).await {
-        let html_raw = pd.content;
-        let html = clean_raw_html(&html_raw);
-        let base_uri = extract_base_uri(uri);
-        let html_obj = Html::parse_fragment(html.as_str());
-        let a_selection = Selector::parse("a");
-        if let Ok(selector) = a_selection {
-            for row in html_obj.select(&selector).into_iter() {
-                if let Some(href) = row.attr("href") {
-                    let title = row.text().collect::<String>();
-                    let title = strip_literal_tags(&title);
-                    if title.len() > 0 && !is_javascript_link(&title, href) && uri.starts_with("#") == false {
-                        let local =  is_local_uri(href, &base_uri);
-                        if links.iter().any(|lk| lk.uri == href) == false {
-                            links.push(LinkItem::new(href, &title, "", local))
-                        }
-                    }
-                }
-            }
-        }
-    } 
-    links
-  }
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+  use serde_json::{json, Value};
+  use crate::page_data::build_page_content_items;
+  use crate::params::TargetConfig;
+
+  const HTML: &str = r#"<html><body><div class="product" data-price="£24.50"><a href="/kettle">Kettle</a><p>Fast<br>Quiet</p></div>
+      <div class="product" data-price="£31"><a href="/toaster">Toaster</a><p>Four slots</p></div><ul class="tags"><li>home</li><li>kitchen</li></ul></body></html>"#;
+
+  // content and matches of the single snippet selected by one target
+  fn extract_item(item: Value) -> (Option<Value>, Vec<Value>) {
+    let items: Vec<TargetConfig> = vec![serde_json::from_value(item).unwrap()];
+    let result = build_page_content_items("https://example.com", HTML, &[], &items, false);
+    let snippet = &result.contents[0];
+    (snippet.content.clone(), snippet.matches.clone())
+  }
+
+  #[test]
+  fn test_multiple_attr_values_as_floats() {
+    let (_, matches) = extract_item(json!({ "path": ".product", "attr": "data-price", "kind": "float", "multiple": true }));
+    assert_eq!(matches, vec![json!(24.5), json!(31.0)]);
+  }
+
+  #[test]
+  fn test_attr_filtered_by_pattern() {
+    let (content, _) = extract_item(json!({ "path": ".product a", "attr": "href", "pattern": "toast" }));
+    assert_eq!(content, Some(json!("/toaster")));
+  }
+
+  #[test]
+  fn test_text_mode_keeps_breaks_and_bullets() {
+    assert_eq!(extract_item(json!({ "path": ".product p", "mode": "text" })).0, Some(json!("Fast\nQuiet")));
+    assert_eq!(extract_item(json!({ "path": ".tags", "mode": "text" })).0, Some(json!("• home\n• kitchen")));
+  }
+
+  #[test]
+  fn test_inner_html_mode() {
+    let (content, _) = extract_item(json!({ "path": ".product", "mode": "inner_html", "pattern": "Four" }));
+    assert_eq!(content, Some(json!(r#"<a href="/toaster">Toaster</a><p>Four slots</p>"#)));
+  }
+}
@@ -0,0 +1,185 @@
+use select::document::Document;
+use select::node::Node;
+use select::predicate::Name;
+use serde::{Deserialize, Serialize};
+use string_patterns::*;
+use crate::metadata::{extract_link_href, has_rel};
+use crate::params::OutputFormat;
+use crate::uris::{document_base_uri, normalize_uri, resolve_uri};
+use crate::cleantext::collapse_whitespace;
+
+const NEXT_LABELS: [&str; 20] = [
+  "next", "next page", "next »", "next ›", "next >", "next →", "older posts", "older entries", "weiter", "nächste",
+  "nächste seite", "suivant", "page suivante", "siguiente", "página siguiente", "successivo", "pagina successiva", "volgende", "próxima", "następna",
+];
+// arrows and bare next classes are only trusted inside a pagination block
+const NEXT_ARROW_PATTERN: &str = r"^[›»→>]{1,2}$";
+const PAGINATION_HINTS: [&str; 6] = ["pagination", "pager", "page-numbers", "paging", "pagenav", "page-nav"];
+const MAIN_CONTENT_TAGS: [&str; 2] = ["main", "article"];
+const CURRENT_PAGE_PATTERN: &str = r"(?:[?&](?:page|p|pg|pagina|seite)=|/page/)(\d+)";
+// hard limit on pages stitched into one result
+pub const MAX_STITCHED_PAGES: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaginationInfo {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub next: Option<String>,
+  // rel, label or numbered
+  pub source: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub current: Option<usize>,
+  #[serde(skip_serializing_if = "Vec::is_empty", default)]
+  pub pages: Vec<String>,
+}
+
+fn node_text(node: &Node) -> String {
+  collapse_whitespace(&node.text()).to_lowercase()
+}
+
+fn node_href(node: &Node, base_uri: &str) -> Option<String> {
+  node.attr("href").map(|href| href.trim())
+    .filter(|href| !href.is_empty() && !href.starts_with('#') && !href.starts_with("javascript:"))
+    .and_then(|href| resolve_uri(href, base_uri))
+}
+
+fn has_name_hint(node: &Node, hints: &[&str]) -> bool {
+  ["class", "id", "aria-label"].iter()
+    .filter_map(|name| node.attr(name))
+    .any(|value| {
+      let value = value.to_lowercase();
+      hints.iter().any(|hint| value.contains(hint))
+    })
+}
+
+fn is_next_label(node: &Node) -> bool {
+  let text = node_text(node);
+  NEXT_LABELS.contains(&text.as_str())
+    || node.attr("aria-label").map(|label| NEXT_LABELS.contains(&label.trim().to_lowercase().as_str())).unwrap_or(false)
+}
+
+// "Next" also labels carousels, galleries and teasers for the next article,
+// so labelled links only count inside a pagination block or the main content
+fn in_pagination_or_main(node: &Node) -> bool {
+  std::iter::successors(node.parent(), |n| n.parent()).any(|anc| {
+    has_name_hint(&anc, &PAGINATION_HINTS)
+      || anc.name().map(|name| MAIN_CONTENT_TAGS.contains(&name)).unwrap_or(false)
+      || anc.attr("role") == Some("main")
+  })
+}
+
+fn is_current_page(node: &Node) -> bool {
+  node.attr("aria-current").is_some() || has_name_hint(node, &["current", "active", "selected"])
+}
+
+fn current_page_from_uri(uri: &str) -> Option<usize> {
+  uri.to_string().pattern_captures(CURRENT_PAGE_PATTERN, true)
+    .and_then(|caps| caps.get(1).map(|m| m.as_str().to_string()))
+    .and_then(|num| num.parse::<usize>().ok())
+}
+
+// Next page link from a block of numbered page links
+fn numbered_next(container: &Node, uri: &str, base_uri: &str) -> Option<PaginationInfo> {
+  let mut numbered: Vec<(usize, Option<String>, bool)> = vec![];
+  for node in container.descendants().filter(|n| matches!(n.name(), Some("a") | Some("span") | Some("em") | Some("strong"))) {
+    if let Ok(num) = node_text(&node).parse::<usize>() {
+      let current = is_current_page(&node) || node.parent().map(|p| is_current_page(&p)).unwrap_or(false);
+      numbered.push((num, node_href(&node, base_uri), current || node.name() != Some("a")));
+    }
+  }
+  let current = numbered.iter().find(|(_, _, current)| *current).map(|(num, _, _)| *num)
+    .or_else(|| current_page_from_uri(uri))
+    .unwrap_or(1);
+  let next = numbered.iter().find(|(num, href, _)| *num == current + 1 && href.is_some()).and_then(|(_, href, _)| href.clone())
+    .or_else(|| {
+      container.descendants().filter(|n| n.name() == Some("a"))
+        .find(|n| node_text(n).pattern_match(NEXT_ARROW_PATTERN, true) || has_name_hint(n, &["next"]))
+        .and_then(|n| node_href(&n, base_uri))
+    });
+  let mut pages: Vec<String> = vec![];
+  for href in numbered.iter().filter_map(|(_, href, _)| href.clone()) {
+    if !pages.contains(&href) {
+      pages.push(href);
+    }
+  }
+  if next.is_some() || pages.len() > 1 {
+    Some(PaginationInfo { next, source: "numbered".to_string(), current: Some(current), pages })
+  } else {
+    None
+  }
+}
+
+// Detect the next page of a paginated article from rel=next links, next anchors or numbered page links
+pub fn detect_pagination(doc: &Document, uri: &str) -> Option<PaginationInfo> {
+  let base_uri = document_base_uri(doc, uri);
+  let page_uri = normalize_uri(uri);
+  let is_other_page = |href: &String| *href != page_uri;
+  let rel_next = extract_link_href(doc, "next")
+    .and_then(|href| resolve_uri(&href, &base_uri))
+    .or_else(|| doc.find(Name("a")).find(|n| has_rel(n, "next")).and_then(|n| node_href(&n, &base_uri)))
+    .filter(is_other_page);
+  if rel_next.is_some() {
+    return Some(PaginationInfo { next: rel_next, source: "rel".to_string(), current: current_page_from_uri(uri), pages: vec![] });
+  }
+  let containers = doc.find(|n: &Node| matches!(n.name(), Some("nav") | Some("div") | Some("ul") | Some("ol") | Some("p")) && has_name_hint(n, &PAGINATION_HINTS))
+    .collect::<Vec<Node>>();
+  let labelled_next = doc.find(Name("a")).filter(|n| !has_rel(n, "prev") && is_next_label(n) && in_pagination_or_main(n))
+    .find_map(|n| node_href(&n, &base_uri).filter(is_other_page));
+  if labelled_next.is_some() {
+    return Some(PaginationInfo { next: labelled_next, source: "label".to_string(), current: current_page_from_uri(uri), pages: vec![] });
+  }
+  containers.iter().find_map(|container| numbered_next(container, uri, &base_uri))
+    .map(|mut info| {
+      info.next = info.next.filter(is_other_page);
+      info
+    })
+}
+
+// Boundary inserted before each stitched page
+pub fn page_boundary(page: usize, format: OutputFormat) -> String {
+  match format {
+    OutputFormat::Html => format!(r#"<hr class="page-break" data-page="{}" />"#, page),
+    OutputFormat::Text => format!("\n\n--- Page {} ---\n\n", page),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use select::document::Document;
+  use crate::pagination::detect_pagination;
+
+  #[test]
+  fn test_numbered_pagination() {
+    let uri = "https://example.com/story?page=2";
+    let doc = Document::from(r#"<html><body><p>Story</p><div class="pagination"><a href="/story?page=1">1</a><span class="current">2</span>
+      <a href="/story?page=3">3</a><a href="/story?page=4">4</a></div></body></html>"#);
+    let info = detect_pagination(&doc, uri).unwrap();
+    assert_eq!(info.source, "numbered");
+    assert_eq!(info.current, Some(2));
+    assert_eq!(info.next.as_deref(), Some("https://example.com/story?page=3"));
+  }
+
+  #[test]
+  fn test_rel_next_link() {
+    let uri = "https://example.com/story?page=2";
+    let doc = Document::from(r#"<html><head><link rel="next" href="https://example.com/story?page=3" /></head><body></body></html>"#);
+    assert_eq!(detect_pagination(&doc, uri).and_then(|info| info.next).as_deref(), Some("https://example.com/story?page=3"));
+  }
+
+  #[test]
+  fn test_labelled_next_outside_pagination_is_ignored() {
+    let uri = "https://example.com/story";
+    let doc = Document::from(r#"<html><body><div class="carousel"><a href="/slide/2">Next</a></div>
+      <aside><a href="/other-story">Next</a></aside></body></html>"#);
+    assert!(detect_pagination(&doc, uri).is_none());
+  }
+
+  #[test]
+  fn test_labelled_next_in_main_content() {
+    let uri = "https://example.com/story";
+    let doc = Document::from(r#"<html><body><div class="carousel"><a href="/slide/2">Next</a></div>
+      <article><p>Story</p><a href="/story/2">Next page</a></article></body></html>"#);
+    let info = detect_pagination(&doc, uri).unwrap();
+    assert_eq!(info.source, "label");
+    assert_eq!(info.next.as_deref(), Some("https://example.com/story/2"));
+  }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use crate::regions::RegionKind;
 use serde_with::skip_serializing_none;
 
 #[skip_serializing_none]
@@ -8,6 +9,11 @@ pub struct QueryParams {
   pub full: Option<u8>,
   pub elements: Option<u8>,
   pub target: Option<String>,
+  pub format: Option<OutputFormat>,
+  pub wrap: Option<usize>,
+  pub metrics: Option<u8>,
+  pub outline: Option<u8>,
+  pub subdomains: Option<u8>,
 }
 #[skip_serializing_none]
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,8 +27,117 @@ pub struct PostParams {
   pub items: Option<Vec<TargetConfig>>,
   pub raw: Option<bool>,
   pub related: Option<bool>,
+  pub sitemap: Option<bool>,
   pub keep_media: Option<bool>,
-  pub skip: Option<bool>
+  pub media: Option<bool>,
+  pub exclude: Option<Vec<RegionKind>>,
+  pub keep_overlays: Option<bool>,
+  pub pages: Option<u8>,
+  pub subdomains: Option<bool>,
+  pub skip: Option<bool>,
+  pub format: Option<OutputFormat>,
+  pub wrap: Option<usize>,
+  pub metrics: Option<bool>,
+  pub outline: Option<bool>,
+  pub csv: Option<bool>,
+  pub filter: Option<LinkFilter>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, PartialEq)]
+pub enum CrawlMode {
+  #[default]
+  #[serde(rename = "page")]
+  Page,
+  #[serde(rename = "items")]
+  Items,
+}
+
+// Crawl limits and scope, with the shared /get-page or /get-content-items options applied to every page
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrawlParams {
+  #[serde(flatten)]
+  pub options: PostParams,
+  pub mode: Option<CrawlMode>,
+  pub depth: Option<u8>,
+  pub max_pages: Option<usize>,
+  pub concurrency: Option<usize>,
+  pub same_domain: Option<bool>,
+  pub include_uris: Option<Vec<String>>,
+  pub exclude_uris: Option<Vec<String>>,
+}
+
+// Endpoints that can run in the background, named after their routes
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobKind {
+  PageStats,
+  GetPage,
+  GetContentItems,
+  GetLinks,
+  GetTables,
+  GetNavigation,
+  Crawl,
+  GetPageFromBrowser,
+  FromBrowser,
+  Batch,
+  GetSitemap,
+  GetFeed,
+}
+
+impl JobKind {
+  // Endpoints taking the PostParams of one page, which can also run in batches
+  pub fn is_single_page(&self) -> bool {
+    !matches!(self, JobKind::Crawl | JobKind::Batch | JobKind::GetSitemap | JobKind::GetFeed)
+  }
+}
+
+// Site or sitemap URI with optional lastmod date range (ISO dates) and URI pattern
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SitemapParams {
+  pub uri: Option<String>,
+  pub since: Option<String>,
+  pub before: Option<String>,
+  pub pattern: Option<String>,
+  pub limit: Option<usize>,
+  pub skip: Option<bool>,
+}
+
+// Feed or page URI, with `text` to fetch the main text of each item's page
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeedParams {
+  pub uri: Option<String>,
+  pub text: Option<bool>,
+  pub limit: Option<usize>,
+  pub skip: Option<bool>,
+}
+
+// Many URIs sharing one set of options for a single-page endpoint, /get-content-items by default
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchParams {
+  #[serde(flatten)]
+  pub options: PostParams,
+  pub uris: Vec<String>,
+  pub endpoint: Option<JobKind>,
+  pub concurrency: Option<usize>,
+}
+
+// The endpoint and its usual request body
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobParams {
+  pub kind: JobKind,
+  pub params: serde_json::Value,
+}
+
+#[skip_serializing_none]
+#[derive(Deserialize, Clone)]
+pub struct JobQuery {
+  pub results: Option<u8>,
+  pub offset: Option<usize>,
+  pub limit: Option<usize>,
 }
 
 #[skip_serializing_none]
@@ -31,6 +146,11 @@ pub struct TargetConfig {
   pub kind: Option<TargetKind>,
   pub path: Option<String>,
   pub paths: Option<Vec<String>>,
+  // XPath 1.0 expression evaluated against the whole document, alongside any CSS paths
+  pub xpath: Option<String>,
+  // read this attribute of each matched element, e.g. href, src or data-price
+  pub attr: Option<String>,
+  pub mode: Option<ExtractMode>,
   pub key: Option<String>,
   pub multiple: Option<bool>,
   pub pattern: Option<String>,
@@ -38,6 +158,15 @@ pub struct TargetConfig {
   pub numeric: Option<bool>,
 }
 
+#[skip_serializing_none]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LinkFilter {
+  pub local: Option<bool>,
+  pub regions: Option<Vec<RegionKind>>,
+  pub pattern: Option<String>,
+  pub rel: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub enum TargetKind {
   #[serde(rename = "main_text")]
@@ -62,6 +191,27 @@ pub enum TargetKind {
   Boolean
 }
 
+// How matched elements are rendered when no attribute is requested
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, PartialEq)]
+pub enum ExtractMode {
+  #[serde(rename = "text")]
+  Text,
+  #[serde(rename = "inner_html")]
+  InnerHtml,
+  #[default]
+  #[serde(rename = "outer_html")]
+  OuterHtml,
+}
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, PartialEq)]
+pub enum OutputFormat {
+  #[default]
+  #[serde(rename = "html")]
+  Html,
+  #[serde(rename = "text")]
+  Text
+}
+
 /* #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum TargetMode {
   FirstMatch,
@@ -0,0 +1,177 @@
+use lopdf::{Document, Object, decode_text_string};
+use serde::{Serialize, Deserialize};
+use crate::pagination::page_boundary;
+use crate::params::OutputFormat;
+use crate::cleantext::collapse_whitespace;
+
+pub const PDF_CONTENT_TYPE: &str = "application/pdf";
+// guard against decompression bombs in untrusted documents
+const MAX_PAGE_CONTENT_BYTES: usize = 16 * 1024 * 1024;
+const MAX_PDF_PAGES: usize = 500;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PdfMetadata {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub title: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub author: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub subject: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub keywords: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub creator: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub producer: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub created: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub modified: Option<String>,
+  #[serde(rename = "numPages")]
+  pub num_pages: usize,
+}
+
+// Extracted text per page, stored in the page cache in place of HTML
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PdfDocument {
+  pub pages: Vec<String>,
+  pub metadata: PdfMetadata,
+}
+
+impl PdfDocument {
+  // All pages with the same boundary markers used when stitching paginated articles
+  pub fn to_text(&self, format: OutputFormat) -> String {
+    self.pages.iter().enumerate().map(|(index, text)| {
+      let body = match format {
+        OutputFormat::Text => text.clone(),
+        OutputFormat::Html => to_paragraphs(text),
+      };
+      if index > 0 {
+        [page_boundary(index + 1, format), body].concat()
+      } else {
+        body
+      }
+    }).collect()
+  }
+}
+
+pub fn is_pdf(bytes: &[u8], content_type: Option<&str>) -> bool {
+  bytes.starts_with(b"%PDF-") || content_type.map(|ct| ct.starts_with(PDF_CONTENT_TYPE)).unwrap_or(false)
+}
+
+fn escape_html(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn to_paragraphs(text: &str) -> String {
+  text.split("\n\n")
+    .map(collapse_whitespace)
+    .filter(|block| !block.is_empty())
+    .map(|block| format!("<p>{}</p>", escape_html(&block)))
+    .collect()
+}
+
+// PDF dates look like D:20240305101500+01'00'
+pub fn pdf_date(raw: &str) -> Option<String> {
+  let digits = raw.trim().trim_start_matches("D:");
+  let num = |start: usize, len: usize, default: &str| digits.get(start..start + len).filter(|d| d.chars().all(|c| c.is_ascii_digit())).unwrap_or(default).to_owned();
+  let year = num(0, 4, "");
+  if year.is_empty() {
+    return None;
+  }
+  let date = format!("{}-{}-{}", year, num(4, 2, "01"), num(6, 2, "01"));
+  if digits.len() < 12 {
+    return Some(date);
+  }
+  let time = format!("{}:{}:{}", num(8, 2, "00"), num(10, 2, "00"), num(12, 2, "00"));
+  let offset = match digits.get(14..) {
+    Some(tz) if tz.starts_with('Z') => "Z".to_owned(),
+    Some(tz) if tz.starts_with('+') || tz.starts_with('-') => {
+      let tz_digits = tz[1..].replace('\'', "");
+      match (tz_digits.get(0..2), tz_digits.get(2..4)) {
+        (Some(h), Some(m)) => format!("{}{}:{}", &tz[..1], h, m),
+        (Some(h), None) => format!("{}{}:00", &tz[..1], h),
+        _ => "".to_owned()
+      }
+    },
+    _ => "".to_owned()
+  };
+  Some(format!("{}T{}{}", date, time, offset))
+}
+
+fn info_string(doc: &Document, info: &lopdf::Dictionary, key: &[u8]) -> Option<String> {
+  info.get_deref(key, doc).ok()
+    .and_then(|obj| decode_text_string(obj).ok())
+    .map(|text| text.trim().to_owned())
+    .filter(|text| !text.is_empty())
+}
+
+fn extract_metadata(doc: &Document, num_pages: usize) -> PdfMetadata {
+  let info = doc.trailer.get_deref(b"Info", doc).ok().and_then(|obj| match obj {
+    Object::Dictionary(dict) => Some(dict),
+    _ => None
+  });
+  match info {
+    Some(info) => PdfMetadata {
+      title: info_string(doc, info, b"Title"),
+      author: info_string(doc, info, b"Author"),
+      subject: info_string(doc, info, b"Subject"),
+      keywords: info_string(doc, info, b"Keywords"),
+      creator: info_string(doc, info, b"Creator"),
+      producer: info_string(doc, info, b"Producer"),
+      created: info_string(doc, info, b"CreationDate").and_then(|d| pdf_date(&d)),
+      modified: info_string(doc, info, b"ModDate").and_then(|d| pdf_date(&d)),
+      num_pages,
+    },
+    None => PdfMetadata { num_pages, ..Default::default() }
+  }
+}
+
+// Text of each page, skipping pages whose content cannot be decoded
+pub fn extract_pdf(bytes: &[u8]) -> Option<PdfDocument> {
+  let mut doc = Document::load_mem(bytes).ok()?;
+  // many PDFs are encrypted with an empty user password only to restrict editing
+  if doc.is_encrypted() && doc.decrypt("").is_err() {
+    return None;
+  }
+  let page_numbers = doc.get_pages().keys().cloned().collect::<Vec<u32>>();
+  let pages = page_numbers.iter().take(MAX_PDF_PAGES)
+    .map(|num| doc.extract_text_with_limit(&[*num], MAX_PAGE_CONTENT_BYTES).unwrap_or_default().trim().to_owned())
+    .collect::<Vec<String>>();
+  let metadata = extract_metadata(&doc, page_numbers.len());
+  Some(PdfDocument { pages, metadata })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::pdf::{PdfDocument, is_pdf, pdf_date};
+  use crate::params::OutputFormat;
+
+  fn two_page_doc() -> PdfDocument {
+    PdfDocument { pages: vec!["Intro <draft>\n\nSecond para".to_owned(), "Page two".to_owned()], ..Default::default() }
+  }
+
+  #[test]
+  fn test_is_pdf() {
+    assert!(is_pdf(b"%PDF-1.7\n...", None));
+    assert!(is_pdf(b"", Some("application/pdf; qs=0.001")));
+    assert!(!is_pdf(b"<!doctype html>", Some("text/html")));
+  }
+
+  #[test]
+  fn test_pdf_date() {
+    assert_eq!(pdf_date("D:20240305101500+01'00'").as_deref(), Some("2024-03-05T10:15:00+01:00"));
+    assert_eq!(pdf_date("D:20240305101500Z").as_deref(), Some("2024-03-05T10:15:00Z"));
+    assert_eq!(pdf_date("D:2024").as_deref(), Some("2024-01-01"));
+  }
+
+  #[test]
+  fn test_pdf_text_output() {
+    assert_eq!(two_page_doc().to_text(OutputFormat::Text), "Intro <draft>\n\nSecond para\n\n--- Page 2 ---\n\nPage two");
+  }
+
+  #[test]
+  fn test_pdf_html_output_is_escaped() {
+    assert!(two_page_doc().to_text(OutputFormat::Html).starts_with("<p>Intro &lt;draft&gt;</p><p>Second para</p><hr"));
+  }
+}
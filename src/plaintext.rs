@@ -0,0 +1,284 @@
+use ego_tree::NodeRef;
+use scraper::{Html, Node};
+use crate::cleantext::collapse_whitespace;
+
+const BLOCK_TAGS: [&str; 34] = ["address", "article", "aside", "blockquote", "caption", "dd", "details", "dialog", "div", "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup", "hr", "main", "nav", "p", "section", "summary", "table", "tbody", "thead", "tr"];
+const SKIP_TAGS: [&str; 11] = ["script", "style", "noscript", "template", "head", "title", "meta", "link", "svg", "iframe", "object"];
+const BULLET: &str = "• ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BlockKind {
+  Paragraph,
+  ListItem,
+  Preformatted,
+}
+
+#[derive(Debug, Clone)]
+struct TextBlock {
+  text: String,
+  prefix: String,
+  kind: BlockKind,
+  new_list: bool,
+}
+
+#[derive(Debug, Clone)]
+struct HeadingBlock {
+  level: u8,
+  anchor: Option<String>,
+  block_index: usize,
+  // headings wrapping divs or paragraphs span several blocks
+  block_end: usize,
+}
+
+// A heading located in the rendered text, with character offsets of its line
+#[derive(Debug, Clone)]
+pub struct HeadingMark {
+  pub level: u8,
+  pub title: String,
+  pub anchor: Option<String>,
+  pub start: usize,
+  pub end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderedText {
+  pub text: String,
+  pub headings: Vec<HeadingMark>,
+}
+
+// Walks the parsed tree and collects text into paragraph, list item and preformatted blocks
+struct PlainTextRenderer {
+  blocks: Vec<TextBlock>,
+  current: String,
+  prefix: String,
+  kind: BlockKind,
+  new_list: bool,
+  lists: Vec<Option<usize>>,
+  headings: Vec<HeadingBlock>,
+}
+
+impl PlainTextRenderer {
+  fn new() -> Self {
+    PlainTextRenderer {
+      blocks: vec![],
+      current: String::new(),
+      prefix: String::new(),
+      kind: BlockKind::Paragraph,
+      new_list: false,
+      lists: vec![],
+      headings: vec![],
+    }
+  }
+
+  fn flush(&mut self) {
+    if !self.current.trim().is_empty() {
+      self.blocks.push(TextBlock {
+        text: self.current.clone(),
+        prefix: self.prefix.clone(),
+        kind: self.kind,
+        new_list: self.new_list,
+      });
+      self.new_list = false;
+    }
+    self.current.clear();
+    self.prefix.clear();
+    if self.kind != BlockKind::Preformatted {
+      self.kind = BlockKind::Paragraph;
+    }
+  }
+
+  fn walk_children(&mut self, node: NodeRef<Node>) {
+    for child in node.children() {
+      self.walk(child);
+    }
+  }
+
+  fn walk(&mut self, node: NodeRef<Node>) {
+    match node.value() {
+      Node::Text(txt) => self.current.push_str(txt),
+      Node::Element(el) => {
+        let tag = el.name();
+        if SKIP_TAGS.contains(&tag) {
+          return;
+        }
+        match tag {
+          "br" => self.current.push('\n'),
+          "ul" | "ol" => {
+            self.flush();
+            let counter = if tag == "ol" {
+              Some(el.attr("start").and_then(|s| s.trim().parse::<usize>().ok()).unwrap_or(1).saturating_sub(1))
+            } else {
+              None
+            };
+            self.lists.push(counter);
+            self.new_list = self.lists.len() == 1;
+            self.walk_children(node);
+            self.lists.pop();
+            self.flush();
+          },
+          "li" => {
+            self.flush();
+            let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+            let marker = match self.lists.last_mut() {
+              Some(Some(num)) => {
+                *num += 1;
+                format!("{}. ", num)
+              },
+              _ => BULLET.to_string(),
+            };
+            self.prefix = [indent, marker].concat();
+            self.kind = BlockKind::ListItem;
+            self.walk_children(node);
+            self.flush();
+          },
+          "pre" => {
+            self.flush();
+            self.kind = BlockKind::Preformatted;
+            self.walk_children(node);
+            self.flush();
+            self.kind = BlockKind::Paragraph;
+          },
+          "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            self.flush();
+            let block_index = self.blocks.len();
+            self.walk_children(node);
+            self.flush();
+            if self.blocks.len() > block_index {
+              let level = tag[1..].parse::<u8>().unwrap_or(1);
+              self.headings.push(HeadingBlock { level, anchor: extract_anchor(node), block_index, block_end: self.blocks.len() });
+            }
+          },
+          "td" | "th" => {
+            if !self.current.trim().is_empty() {
+              self.current.push_str(" | ");
+            }
+            self.walk_children(node);
+          },
+          _ => if BLOCK_TAGS.contains(&tag) {
+            self.flush();
+            self.walk_children(node);
+            self.flush();
+          } else {
+            self.walk_children(node);
+          }
+        }
+      },
+      Node::Document | Node::Fragment => self.walk_children(node),
+      _ => (),
+    }
+  }
+
+  fn render(&self, wrap_width: Option<usize>) -> RenderedText {
+    let mut output = String::new();
+    let mut output_len: usize = 0;
+    let mut block_offsets: Vec<(usize, usize)> = Vec::with_capacity(self.blocks.len());
+    let mut prev_kind: Option<BlockKind> = None;
+    for block in &self.blocks {
+      let text = match block.kind {
+        BlockKind::Preformatted => block.text.trim_matches('\n').to_string(),
+        _ => {
+          let indent = " ".repeat(block.prefix.chars().count());
+          block.text.split('\n')
+            .map(collapse_whitespace)
+            .filter(|line| !line.is_empty())
+            .enumerate()
+            .map(|(index, line)| {
+              let first_prefix = if index == 0 { block.prefix.as_str() } else { indent.as_str() };
+              wrap_line(&line, wrap_width, first_prefix, &indent)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+        }
+      };
+      if text.is_empty() {
+        block_offsets.push((output_len, output_len));
+        continue;
+      }
+      if let Some(pk) = prev_kind {
+        let separator = if pk == BlockKind::ListItem && block.kind == BlockKind::ListItem && !block.new_list { "\n" } else { "\n\n" };
+        output.push_str(separator);
+        output_len += separator.len();
+      }
+      let text_len = text.chars().count();
+      block_offsets.push((output_len, output_len + text_len));
+      output.push_str(&text);
+      output_len += text_len;
+      prev_kind = Some(block.kind);
+    }
+    let headings = self.headings.iter().filter_map(|hb| {
+      let (start, _) = block_offsets.get(hb.block_index).copied()?;
+      let (_, end) = block_offsets.get(hb.block_end - 1).copied()?;
+      let title = self.blocks[hb.block_index..hb.block_end].iter().map(|block| collapse_whitespace(&block.text)).collect::<Vec<String>>().join(" ");
+      Some(HeadingMark { level: hb.level, title, anchor: hb.anchor.clone(), start, end })
+    }).collect();
+    RenderedText { text: output, headings }
+  }
+}
+
+// The heading's own id, or the first id or named anchor inside it
+fn extract_anchor(node: NodeRef<Node>) -> Option<String> {
+  node.descendants()
+    .filter_map(|n| n.value().as_element())
+    .find_map(|el| el.attr("id").or(if el.name() == "a" { el.attr("name") } else { None }))
+    .map(|id| id.trim().to_string())
+    .filter(|id| !id.is_empty())
+}
+
+// Greedy word wrap, with continuation lines indented to align with the first line's text
+fn wrap_line(line: &str, wrap_width: Option<usize>, first_prefix: &str, indent: &str) -> String {
+  let width = wrap_width.unwrap_or(0);
+  if width < 1 {
+    return [first_prefix, line].concat();
+  }
+  let mut lines: Vec<String> = vec![];
+  let mut current = first_prefix.to_string();
+  let mut current_len = first_prefix.chars().count();
+  let mut has_words = false;
+  for word in line.split(' ') {
+    let word_len = word.chars().count();
+    if has_words && current_len + 1 + word_len > width {
+      lines.push(current);
+      current = indent.to_string();
+      current_len = indent.chars().count();
+      has_words = false;
+    }
+    if has_words {
+      current.push(' ');
+      current_len += 1;
+    }
+    current.push_str(word);
+    current_len += word_len;
+    has_words = true;
+  }
+  lines.push(current);
+  lines.join("\n")
+}
+
+// Render an HTML fragment as normalised plain text with paragraph breaks and bulleted list items,
+// keeping the character offsets of headings
+pub fn render_plain_text(html: &str, wrap_width: Option<usize>) -> RenderedText {
+  let fragment = Html::parse_fragment(html);
+  let mut renderer = PlainTextRenderer::new();
+  renderer.walk(fragment.tree.root());
+  renderer.flush();
+  renderer.render(wrap_width)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::plaintext::render_plain_text;
+
+  #[test]
+  fn test_paragraphs_and_lists() {
+    let html = r#"<div><h2>Fuel  prices</h2><p>Prices rose&nbsp;by <b>3%</b> &amp; fell.</p><ul><li>Petrol</li><li>Diesel</li></ul><ol start="4"><li>Fourth</li></ol><script>var x = 1;</script></div>"#;
+    let expected = "Fuel prices\n\nPrices rose by 3% & fell.\n\n• Petrol\n• Diesel\n\n4. Fourth";
+    assert_eq!(render_plain_text(html, None).text, expected);
+  }
+
+  #[test]
+  fn test_wrapping() {
+    let html = "<ul><li>one two three four five</li></ul>";
+    let expected = "• one two\n  three four\n  five";
+    assert_eq!(render_plain_text(html, Some(12)).text, expected);
+  }
+}
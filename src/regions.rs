@@ -0,0 +1,237 @@
+use std::collections::HashSet;
+use ego_tree::NodeId;
+use scraper::{Html, Selector};
+use html5ever::tree_builder::TreeSink;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::Name;
+use serde::{Deserialize, Serialize};
+use crate::stats::{PageElement, is_content_element};
+
+// an unlabelled block holding most of the page text is treated as a wrapper and its children classified instead
+const WRAPPER_TEXT_RATIO: f64 = 0.6;
+const MAX_REGION_DEPTH: usize = 6;
+const LANDMARK_TAGS: [&str; 4] = ["header", "nav", "aside", "footer"];
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionKind {
+  Header,
+  Navigation,
+  Main,
+  Sidebar,
+  Footer,
+  Comments,
+  Advert,
+  CookieBanner,
+  RelatedLinks,
+  Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PageRegion {
+  pub kind: RegionKind,
+  pub selector: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub role: Option<String>,
+  pub depth: usize,
+  #[serde(rename = "textLen")]
+  pub text_len: usize,
+  #[serde(rename = "linkDensity")]
+  pub link_density: f64,
+  pub fraction: f64,
+}
+
+impl PageRegion {
+  pub fn new(element: &PageElement, kind: RegionKind) -> PageRegion {
+    let link_density = if element.text_len > 0 { 1f64 - element.plain_text_ratio() } else { 0f64 };
+    PageRegion {
+      kind,
+      selector: element.selector(),
+      role: element.role.clone(),
+      depth: element.depth,
+      text_len: element.text_len,
+      link_density: (link_density.clamp(0.0, 1.0) * 100.0).round() / 100.0,
+      fraction: (element.fraction * 1000.0).round() / 1000.0,
+    }
+  }
+
+  // Bare tags other than landmarks would match unrelated blocks
+  pub fn has_specific_selector(&self) -> bool {
+    self.selector.contains(['#', '.']) || LANDMARK_TAGS.contains(&self.selector.as_str())
+  }
+}
+
+fn role_hint(role: &str) -> Option<RegionKind> {
+  match role {
+    "banner" => Some(RegionKind::Header),
+    "navigation" | "menubar" => Some(RegionKind::Navigation),
+    "main" | "article" => Some(RegionKind::Main),
+    "complementary" => Some(RegionKind::Sidebar),
+    "contentinfo" => Some(RegionKind::Footer),
+    "alertdialog" => Some(RegionKind::CookieBanner),
+    _ => None
+  }
+}
+
+fn tag_hint(tag: &str) -> Option<RegionKind> {
+  match tag {
+    "header" => Some(RegionKind::Header),
+    "nav" | "menu" => Some(RegionKind::Navigation),
+    "main" | "article" => Some(RegionKind::Main),
+    "aside" => Some(RegionKind::Sidebar),
+    "footer" => Some(RegionKind::Footer),
+    _ => None
+  }
+}
+
+fn name_hint(name: &str) -> Option<RegionKind> {
+  let tokens = name.split(['-', '_', ' ']).filter(|t| !t.is_empty()).collect::<Vec<&str>>();
+  let has_token = |options: &[&str]| tokens.iter().any(|t| options.contains(t));
+  let has_part = |options: &[&str]| options.iter().any(|opt| name.contains(opt));
+  if has_part(&["cookie", "consent", "gdpr"]) {
+    Some(RegionKind::CookieBanner)
+  } else if has_token(&["ad", "ads", "dfp", "adslot", "adunit"]) || has_part(&["advert", "sponsor", "adsense", "banner-ad"]) {
+    Some(RegionKind::Advert)
+  } else if has_part(&["comment", "disqus", "discussion"]) {
+    Some(RegionKind::Comments)
+  } else if has_part(&["related", "recommend", "read-next", "outbrain", "taboola", "more-stories"]) {
+    Some(RegionKind::RelatedLinks)
+  } else if has_token(&["nav", "navbar", "navigation", "menu", "breadcrumb", "breadcrumbs"]) {
+    Some(RegionKind::Navigation)
+  } else if has_token(&["sidebar", "aside", "rail", "side", "widgets"]) {
+    Some(RegionKind::Sidebar)
+  } else if has_token(&["footer", "colophon"]) {
+    Some(RegionKind::Footer)
+  } else if has_token(&["header", "masthead", "topbar"]) {
+    Some(RegionKind::Header)
+  } else if has_token(&["main", "content", "article", "post", "story", "entry", "body"]) {
+    Some(RegionKind::Main)
+  } else {
+    None
+  }
+}
+
+// Region suggested by the ARIA role, tag name or class and id names, in that order of precedence
+pub fn region_hint(tag: &str, role: Option<&str>, class_names: &[String], id: Option<&str>) -> Option<RegionKind> {
+  role.and_then(|r| role_hint(&r.trim().to_lowercase()))
+    .or_else(|| tag_hint(tag))
+    .or_else(|| id.iter().map(|s| s.to_string()).chain(class_names.iter().cloned()).find_map(|name| name_hint(&name.to_lowercase())))
+}
+
+// Fallback for blocks without hints, based on link density and text share
+fn classify_by_features(element: &PageElement) -> RegionKind {
+  let link_dense = element.num_links >= 3 && element.plain_text_ratio() < 0.5;
+  if link_dense && element.list_links >= 3 {
+    RegionKind::Navigation
+  } else if link_dense {
+    RegionKind::RelatedLinks
+  } else if element.is_main_text_element() || element.has_meaningful_text() {
+    RegionKind::Main
+  } else {
+    RegionKind::Unknown
+  }
+}
+
+fn num_content_children(node: &Node) -> usize {
+  node.children().filter(|child| is_content_element(child) && PageElement::new(child, 0).has_meaningful_content()).count()
+}
+
+fn collect_regions(regions: &mut Vec<PageRegion>, parent: &Node, depth: usize, total_text_len: usize) {
+  for child in parent.children().filter(is_content_element) {
+    let mut element = PageElement::new(&child, depth);
+    let hint = region_hint(&element.tag_name, element.role.as_deref(), &element.class_names, element.id.as_deref());
+    // short blocks only count when marked up as a region, e.g. a cookie notice
+    let is_empty = element.text_len < 1 && element.num_links < 1;
+    if is_empty || (hint.is_none() && !element.has_meaningful_content()) {
+      continue;
+    }
+    element.set_fraction(total_text_len);
+    let may_wrap = hint.is_none() || hint == Some(RegionKind::Main);
+    if may_wrap && depth < MAX_REGION_DEPTH && element.fraction > WRAPPER_TEXT_RATIO && num_content_children(&child) > 1 {
+      collect_regions(regions, &child, depth + 1, total_text_len);
+    } else {
+      let kind = hint.unwrap_or_else(|| classify_by_features(&element));
+      regions.push(PageRegion::new(&element, kind));
+    }
+  }
+}
+
+// Classify the top-level blocks of the body, descending into generic wrappers
+pub fn classify_regions(doc: &Document) -> Vec<PageRegion> {
+  let mut regions: Vec<PageRegion> = vec![];
+  if let Some(body) = doc.find(Name("body")).next() {
+    let total_text_len = PageElement::new(&body, 0).text_len.max(1);
+    collect_regions(&mut regions, &body, 0, total_text_len);
+  }
+  regions
+}
+
+// A copy of the html without the regions of the given kinds, or None if nothing was excluded.
+// Regions holding the element matched by `keep_selector`, such as the best content block, are never removed
+pub fn exclude_regions(html_obj: &Html, regions: &[PageRegion], kinds: &[RegionKind], keep_selector: Option<&str>) -> Option<Html> {
+  let kept_ids: HashSet<NodeId> = keep_selector.and_then(|ks| Selector::parse(ks).ok())
+    .map(|sel| html_obj.select(&sel).flat_map(|el| el.ancestors().map(|node| node.id()).chain([el.id()])).collect())
+    .unwrap_or_default();
+  let selectors = regions.iter()
+    .filter(|region| kinds.contains(&region.kind) && region.has_specific_selector())
+    .filter_map(|region| Selector::parse(&region.selector).ok())
+    .filter(|sel| !html_obj.select(sel).any(|el| kept_ids.contains(&el.id())))
+    .collect::<Vec<Selector>>();
+  if selectors.is_empty() {
+    return None;
+  }
+  let mut content_obj = html_obj.clone();
+  for sel in selectors {
+    let ids = content_obj.select(&sel).map(|el| el.id()).collect::<Vec<_>>();
+    for id in ids {
+      content_obj.remove_from_parent(&id);
+    }
+  }
+  Some(content_obj)
+}
+
+#[cfg(test)]
+mod tests {
+  use scraper::Html;
+  use select::document::Document;
+  use crate::regions::{PageRegion, RegionKind, classify_regions, exclude_regions, region_hint};
+
+  #[test]
+  fn test_region_hints() {
+    assert_eq!(region_hint("div", Some("contentinfo"), &[], None), Some(RegionKind::Footer));
+    assert_eq!(region_hint("div", None, &["cookie-notice".to_string()], None), Some(RegionKind::CookieBanner));
+    assert_eq!(region_hint("div", None, &["ad-slot".to_string()], None), Some(RegionKind::Advert));
+    assert_eq!(region_hint("div", None, &["header-ads".to_string()], None), Some(RegionKind::Advert));
+  }
+
+  #[test]
+  fn test_classify_regions() {
+    let para = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. ".repeat(8);
+    let html = format!(r#"<html><body><div class="page"><header><a href="/">Home</a></header>
+      <div class="wrap"><p>{para}</p><p>{para}</p></div>
+      <div id="comments"><p>Nice post, thanks for sharing it with us</p></div></div>
+      <footer><a href="/about">About</a> <a href="/contact">Contact</a></footer></body></html>"#);
+    let regions = classify_regions(&Document::from(html.as_str()));
+    let kinds = regions.iter().map(|r| r.kind).collect::<Vec<RegionKind>>();
+    assert_eq!(kinds, vec![RegionKind::Header, RegionKind::Main, RegionKind::Comments, RegionKind::Footer]);
+    assert_eq!(regions[2].selector, "div#comments");
+  }
+
+  fn region(kind: RegionKind, selector: &str) -> PageRegion {
+    PageRegion { kind, selector: selector.to_owned(), role: None, depth: 0, text_len: 0, link_density: 0.0, fraction: 0.0 }
+  }
+
+  #[test]
+  fn test_exclude_regions_keeps_best_content() {
+    let html = Html::parse_fragment(r#"<div class="col"><article class="story"><p>Story text</p></article></div>
+      <div class="col"><a href="/a">A</a> <a href="/b">B</a></div><div id="comments"><p>First!</p></div>"#);
+    let regions = vec![region(RegionKind::RelatedLinks, "div.col"), region(RegionKind::Comments, "div#comments")];
+    let kinds = [RegionKind::RelatedLinks, RegionKind::Comments];
+    let content = exclude_regions(&html, &regions, &kinds, Some("article.story")).unwrap().html();
+    assert!(content.contains("Story text") && content.contains("href=\"/a\""));
+    assert!(!content.contains("First!"));
+    let content = exclude_regions(&html, &regions, &kinds, None).unwrap().html();
+    assert!(!content.contains("Story text"));
+  }
+}
@@ -1,23 +1,31 @@
-use serde_json::json;
+use serde_json::{json, Value};
 use axum::{
-    response::IntoResponse,
-    http::StatusCode,
+    response::{IntoResponse, Response},
+    http::{HeaderMap, StatusCode},
     extract,
     Json,
 };
 use crate::browsergrab::capture_from_headless_browser;
 use crate::{page_data::*, params::*};
-use crate::stats::{extract_base_uri, concat_full_uri};
+use crate::stats::{extract_base_uri, is_local_uri};
+use crate::uris::normalize_uri;
+use crate::batch::{BatchConfig, run_batch};
+use crate::crawler::{CrawlConfig, crawl_site, crawl_site_with};
+use crate::jobs::{cancel_job, create_job, get_job, get_job_results};
+use crate::feeds::fetch_feed;
+use crate::sitemap::{fetch_sitemap_urls, sitemap_seed_uris};
+use crate::streaming::{EventSender, stream_response};
 // use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 
 const RELATED_SCAN_LIMIT: usize = 64;
+const JOB_RESULTS_LIMIT: usize = 100;
 
 pub async fn handler_404() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "nothing to see here")
 }
 
-pub async fn page_data_response(params: extract::Query<QueryParams>) -> impl IntoResponse {
+pub async fn page_data_value(params: &QueryParams) -> Value {
     let mut response = json!({
         "valid": false,
     });
@@ -26,48 +34,91 @@ pub async fn page_data_response(params: extract::Query<QueryParams>) -> impl Int
         let show_elements = params.elements.unwrap_or(1) > 0;
         let target = params.target.clone();
         let show_mode = ShowMode::new(show_elements, true);
-        let page_data_response = fetch_page_data(&uri, show_mode, strip_extra, target, false, false).await;
+        let options = ContentOptions::from_query(params);
+        let page_data_response = fetch_page_data(&uri, show_mode, strip_extra, target, false, false, &options).await;
         response = json!(page_data_response)
     }
-    (StatusCode::OK, Json(response))
+    response
 }
 
-pub async fn page_data_response_post(params: extract::Json<PostParams>) -> impl IntoResponse {
+pub async fn page_data_response(params: extract::Query<QueryParams>) -> impl IntoResponse {
+    (StatusCode::OK, Json(page_data_value(&params).await))
+}
+
+// Pages for a related scan, from the site's sitemaps if requested or else the page's own domain links
+async fn related_uris(params: &PostParams, page_data_response: &PageResultSet) -> Vec<String> {
+    match (params.sitemap.unwrap_or(false), params.uri.as_ref()) {
+        (true, Some(uri)) => sitemap_seed_uris(uri, RELATED_SCAN_LIMIT, params.skip.unwrap_or(false)).await,
+        // domain links are already absolute
+        _ => page_data_response.domain_links().into_iter().take(RELATED_SCAN_LIMIT).collect()
+    }
+}
+
+// Page stats options resolved from the request, shared by the JSON and streaming endpoints
+struct PageStatsRequest {
+    uri: String,
+    show_mode: ShowMode,
+    strip_extra: bool,
+    target: Option<String>,
+    show_raw: bool,
+    skip_cache: bool,
+    fetch_related: bool,
+    options: ContentOptions,
+}
+
+impl PageStatsRequest {
+    fn new(params: &PostParams) -> Option<Self> {
+        let show_elements = params.elements.unwrap_or(true);
+        Some(PageStatsRequest {
+            uri: params.uri.clone()?,
+            show_mode: ShowMode::new(show_elements, show_elements),
+            strip_extra: !params.full.unwrap_or(false),
+            target: params.target.clone(),
+            show_raw: params.raw.unwrap_or(false),
+            skip_cache: params.skip.unwrap_or(false),
+            fetch_related: params.related.unwrap_or(false),
+            options: ContentOptions::from_params(params),
+        })
+    }
+
+    async fn fetch_page(&self) -> PageResultSet {
+        fetch_page_data(&self.uri, self.show_mode, self.strip_extra, self.target.clone(), self.show_raw, self.skip_cache, &self.options).await
+    }
+
+    async fn related_uris(&self, params: &PostParams, page_data_response: &PageResultSet) -> Vec<String> {
+        if self.fetch_related {
+            related_uris(params, page_data_response).await
+        } else {
+            vec![]
+        }
+    }
+
+    // related pages are summarised without elements or links
+    async fn fetch_related_page(&self, uri: &str) -> PageResultSet {
+        fetch_page_data(uri, ShowMode::new(false, false), self.strip_extra, None, false, false, &self.options).await
+    }
+}
+
+pub async fn page_data_post_value(params: &PostParams) -> Value {
     let mut response = json!({
         "valid": false,
     });
-    if let Some(uri) = params.uri.clone() {
-        let strip_extra = params.full.unwrap_or(false) == false;
-        let show_elements = params.elements.unwrap_or(true);
-        let show_links = params.elements.unwrap_or(true);
-        let target = params.target.clone();
-        let show_raw = params.raw.unwrap_or(false);
-        let fetch_related = params.related.unwrap_or(false);
-        let base_uri = extract_base_uri(&uri);
-        
-
-        let show_mode = ShowMode::new(show_elements, show_links);
-        let skip_cache = params.skip.unwrap_or(false);
-        let mut page_data_response = fetch_page_data(&uri, show_mode, strip_extra, target, show_raw, skip_cache).await;
-        if fetch_related {
-            let show_mode = ShowMode::new(false, false);
-            let mut counter: usize = 0;
-            for dl in page_data_response.domain_links() {
-                if counter < RELATED_SCAN_LIMIT {
-                  let new_uri = concat_full_uri(&dl, &base_uri);
-                  let result_set = fetch_page_data(&new_uri, show_mode, strip_extra, None, false, false).await;
-                  page_data_response.add_related(result_set);
-                  counter += 1;
-                }
-            }
+    if let Some(request) = PageStatsRequest::new(params) {
+        let mut page_data_response = request.fetch_page().await;
+        for dl in request.related_uris(params, &page_data_response).await {
+            page_data_response.add_related(request.fetch_related_page(&dl).await);
         }
         response = json!(page_data_response);
     }
-    (StatusCode::OK, Json(response))
+    response
 }
 
+pub async fn page_data_response_post(params: extract::Json<PostParams>) -> impl IntoResponse {
+    (StatusCode::OK, Json(page_data_post_value(&params).await))
+}
 
-pub async fn page_content_response_post(params: extract::Json<PostParams>) -> impl IntoResponse {
+
+pub async fn page_content_value(params: &PostParams) -> Value {
   let mut response = json!({
       "valid": false,
   });
@@ -78,27 +129,56 @@ pub async fn page_content_response_post(params: extract::Json<PostParams>) -> im
 
       let show_mode = ShowMode::new(false, show_links);
       let skip_cache = params.skip.unwrap_or(false);
-      let page_data_response = fetch_page_data(&uri, show_mode, true, target, false, skip_cache).await;
+      let options = ContentOptions::from_params(params);
+      let mut page_data_response = fetch_page_data(&uri, show_mode, true, target.clone(), false, skip_cache, &options).await;
+      if options.pages > 1 {
+        let base_uri = extract_base_uri(&uri);
+        let mut page_uris = vec![normalize_uri(&uri)];
+        while page_uris.len() < options.pages {
+          // only pages of the same site are stitched on
+          match page_data_response.next_page_uri().filter(|next| !page_uris.contains(next) && is_local_uri(next, &base_uri, false)) {
+            Some(next_uri) => {
+              let next_result = fetch_page_data(&next_uri, show_mode, true, target.clone(), false, skip_cache, &options).await;
+              if !page_data_response.append_page(next_result, page_uris.len() + 1, options.format) {
+                break;
+              }
+              page_uris.push(next_uri);
+            },
+            None => break
+          }
+        }
+        if page_uris.len() > 1 {
+          page_data_response.set_page_uris(page_uris);
+        }
+      }
       
       response = json!(page_data_response);
   }
-  (StatusCode::OK, Json(response))
+  response
+}
+
+pub async fn page_content_response_post(params: extract::Json<PostParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(page_content_value(&params).await))
 }
 
 
-pub async fn page_links_response_post(params: extract::Json<PostParams>) -> impl IntoResponse {
+pub async fn page_links_value(params: &PostParams) -> Value {
   let mut response = json!({
       "valid": false,
   });
   if let Some(uri) = params.uri.clone() {
-    let links = fetch_page_links(&uri).await;
+    let links = fetch_page_links(&uri, params.subdomains.unwrap_or(true), params.filter.as_ref()).await;
     
     response = json!({ "links": links });
   }
-  (StatusCode::OK, Json(response))
+  response
 }
 
-pub async fn fetch_page_from_browser(params: extract::Json<PostParams>) -> impl IntoResponse {
+pub async fn page_links_response_post(params: extract::Json<PostParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(page_links_value(&params).await))
+}
+
+pub async fn page_from_browser_value(params: &PostParams) -> Value {
   let mut response = json!({
       "valid": false,
   });
@@ -108,10 +188,14 @@ pub async fn fetch_page_from_browser(params: extract::Json<PostParams>) -> impl
       response = json!({ "valid": true,"content": pd.content, "ts": pd.ts, "cached": pd.cached, "uri": pd.uri });
     }
   }
-  (StatusCode::OK, Json(response))
+  response
 }
 
-pub async fn fetch_page_content_from_browser(params: extract::Json<PostParams>) -> impl IntoResponse {
+pub async fn fetch_page_from_browser(params: extract::Json<PostParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(page_from_browser_value(&params).await))
+}
+
+pub async fn page_content_from_browser_value(params: &PostParams) -> Value {
   let mut response = json!({
       "valid": false,
   });
@@ -123,14 +207,19 @@ pub async fn fetch_page_content_from_browser(params: extract::Json<PostParams>)
       let target = params.target.clone();
       let show_mode = ShowMode::new(show_elements, true);
       let show_raw = params.raw.unwrap_or(false);
-      let result = build_page_content_data(&uri, &pd.content, show_mode, strip_extra, target, show_raw, pd.cached);
+      let options = ContentOptions::from_params(params);
+      let result = build_page_content_data(&uri, &pd.content, show_mode, strip_extra, target, show_raw, pd.cached, &options);
       response = json!(result);
     }
   }
-  (StatusCode::OK, Json(response))
+  response
 }
 
-pub async fn fetch_page_content_items(params: extract::Json<PostParams>) -> impl IntoResponse {
+pub async fn fetch_page_content_from_browser(params: extract::Json<PostParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(page_content_from_browser_value(&params).await))
+}
+
+pub async fn page_content_items_value(params: &PostParams) -> Value {
   let mut response = json!({
       "valid": false,
   });
@@ -138,15 +227,205 @@ pub async fn fetch_page_content_items(params: extract::Json<PostParams>) -> impl
       let targets = params.targets.clone().unwrap_or(vec![]);
       let items = params.items.clone().unwrap_or(vec![]);
       let skip_cache = params.skip.unwrap_or(false);
-      let mut raw_html = "".to_string();
-      let mut cached = false;
-      if let Some(pd) = fetch_page(&uri, skip_cache).await {
-        raw_html = pd.content;
-        cached = pd.cached;
-      }
-      let page_data_response = build_page_content_items(&uri, &raw_html, &targets, &items, cached);
+      let page_data_response = match fetch_page(&uri, skip_cache).await {
+        Some(pd) => build_content_items(&uri, &pd, &targets, &items),
+        None => build_page_content_items(&uri, "", &targets, &items, false)
+      };
       
       response = json!(page_data_response);
   }
+  response
+}
+
+pub async fn fetch_page_content_items(params: extract::Json<PostParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(page_content_items_value(&params).await))
+}
+pub async fn page_tables_value(params: &PostParams) -> Value {
+  let mut response = json!({
+      "valid": false,
+  });
+  if let Some(uri) = params.uri.clone() {
+      let skip_cache = params.skip.unwrap_or(false);
+      let with_csv = params.csv.unwrap_or(false);
+      if let Some(pd) = fetch_page(&uri, skip_cache).await.filter(is_html_page) {
+        let result = build_page_tables(&pd.content, params.target.clone(), with_csv, pd.cached);
+        response = json!(result);
+      }
+  }
+  response
+}
+
+pub async fn fetch_page_tables(params: extract::Json<PostParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(page_tables_value(&params).await))
+}
+
+pub async fn page_navigation_value(params: &PostParams) -> Value {
+  let mut response = json!({
+      "valid": false,
+  });
+  if let Some(uri) = params.uri.clone() {
+      let skip_cache = params.skip.unwrap_or(false);
+      if let Some(pd) = fetch_page(&uri, skip_cache).await.filter(is_html_page) {
+        let result = build_page_navigation(&uri, &pd.content, pd.cached);
+        response = json!(result);
+      }
+  }
+  response
+}
+
+pub async fn fetch_page_navigation(params: extract::Json<PostParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(page_navigation_value(&params).await))
+}
+
+pub async fn crawl_site_value(params: &CrawlParams) -> Value {
+  let mut response = json!({
+      "valid": false,
+  });
+  if let Some(config) = CrawlConfig::new(params) {
+      let result = crawl_site(&config).await;
+      response = json!(result);
+  }
+  response
+}
+
+pub async fn crawl_site_response(params: extract::Json<CrawlParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(crawl_site_value(&params).await))
+}
+
+pub async fn sitemap_value(params: &SitemapParams) -> Value {
+  let mut response = json!({
+      "valid": false,
+  });
+  if params.uri.is_some() {
+    response = json!(fetch_sitemap_urls(params).await);
+  }
+  response
+}
+
+pub async fn fetch_sitemap(params: extract::Json<SitemapParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(sitemap_value(&params).await))
+}
+
+pub async fn feed_value(params: &FeedParams) -> Value {
+  match fetch_feed(params).await {
+    Some(result) => json!(result),
+    None => json!({ "valid": false })
+  }
+}
+
+pub async fn fetch_feed_response(params: extract::Json<FeedParams>) -> impl IntoResponse {
+  (StatusCode::OK, Json(feed_value(&params).await))
+}
+
+// The response of a single-page endpoint, or None for endpoints spanning many pages
+pub async fn endpoint_value(kind: JobKind, params: &PostParams) -> Option<Value> {
+  match kind {
+    JobKind::PageStats => Some(page_data_post_value(params).await),
+    JobKind::GetPage => Some(page_content_value(params).await),
+    JobKind::GetContentItems => Some(page_content_items_value(params).await),
+    JobKind::GetLinks => Some(page_links_value(params).await),
+    JobKind::GetTables => Some(page_tables_value(params).await),
+    JobKind::GetNavigation => Some(page_navigation_value(params).await),
+    JobKind::GetPageFromBrowser => Some(page_content_from_browser_value(params).await),
+    JobKind::FromBrowser => Some(page_from_browser_value(params).await),
+    JobKind::Crawl | JobKind::Batch | JobKind::GetSitemap | JobKind::GetFeed => None,
+  }
+}
+
+pub async fn batch_response(params: extract::Json<BatchParams>) -> impl IntoResponse {
+  let response = match BatchConfig::new(&params) {
+    Ok(config) => json!(run_batch(&config, |_, _| async { true }).await),
+    Err(error) => json!({ "valid": false, "error": error }),
+  };
   (StatusCode::OK, Json(response))
-}
\ No newline at end of file
+}
+
+// Each URI's result as soon as it is ready, then totals
+async fn stream_batch(params: BatchParams, events: EventSender) {
+  match BatchConfig::new(&params) {
+    Ok(config) => {
+      let result = run_batch(&config, |item, _| events.send("item", item)).await;
+      events.send("summary", &result.summary()).await;
+    },
+    Err(error) => {
+      events.send("summary", &json!({ "valid": false, "error": error })).await;
+    }
+  }
+}
+
+pub async fn batch_stream(headers: HeaderMap, extract::Json(params): extract::Json<BatchParams>) -> Response {
+  stream_response(&headers, move |events| stream_batch(params, events))
+}
+
+pub async fn create_job_response(params: extract::Json<JobParams>) -> impl IntoResponse {
+  let response = match create_job(params.kind, params.params.clone()).await {
+    Ok(job) => json!({ "valid": true, "id": job.id, "status": job.status }),
+    Err(error) => json!({ "valid": false, "error": error }),
+  };
+  (StatusCode::OK, Json(response))
+}
+
+pub async fn job_status_response(extract::Path(id): extract::Path<String>, query: extract::Query<JobQuery>) -> impl IntoResponse {
+  let mut response = json!({
+      "valid": false,
+  });
+  if let Some(job) = get_job(&id).await {
+    response = json!(job);
+    // pollers can page through results with offset to fetch only new items
+    if query.results.unwrap_or(0) > 0 {
+      let limit = query.limit.unwrap_or(JOB_RESULTS_LIMIT);
+      response["results"] = json!(get_job_results(&id, query.offset.unwrap_or(0), limit).await);
+    }
+  }
+  (StatusCode::OK, Json(response))
+}
+
+pub async fn cancel_job_response(extract::Path(id): extract::Path<String>) -> impl IntoResponse {
+  let response = match cancel_job(&id).await {
+    Some(job) => json!({ "valid": true, "id": job.id, "status": job.status }),
+    None => json!({ "valid": false }),
+  };
+  (StatusCode::OK, Json(response))
+}
+
+// The main page result followed by each related page as it is scanned, then a summary
+async fn stream_page_data(params: PostParams, events: EventSender) {
+  let Some(request) = PageStatsRequest::new(&params) else {
+    events.send("summary", &json!({ "valid": false })).await;
+    return;
+  };
+  let page_data_response = request.fetch_page().await;
+  let valid = page_data_response.is_valid();
+  let related_uris = request.related_uris(&params, &page_data_response).await;
+  let mut num_related: usize = 0;
+  if events.send("page", &page_data_response).await {
+    for dl in related_uris {
+      if !events.send("related", &request.fetch_related_page(&dl).await).await {
+        return;
+      }
+      num_related += 1;
+    }
+  }
+  events.send("summary", &json!({ "uri": request.uri, "related": num_related, "valid": valid })).await;
+}
+
+pub async fn page_data_stream_post(headers: HeaderMap, extract::Json(params): extract::Json<PostParams>) -> Response {
+  stream_response(&headers, move |events| stream_page_data(params, events))
+}
+
+// Each crawled page as soon as it has been extracted, then the crawl summary
+async fn stream_crawl(params: CrawlParams, events: EventSender) {
+  match CrawlConfig::new(&params) {
+    Some(config) => {
+      let result = crawl_site_with(&config, |page, _| events.send("page", page)).await;
+      events.send("summary", &result.summary()).await;
+    },
+    None => {
+      events.send("summary", &json!({ "valid": false })).await;
+    }
+  }
+}
+
+pub async fn crawl_site_stream(headers: HeaderMap, extract::Json(params): extract::Json<CrawlParams>) -> Response {
+  stream_response(&headers, move |events| stream_crawl(params, events))
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashSet, VecDeque};
+use std::io::Read;
+use chrono::Duration;
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use string_patterns::*;
+use crate::byline::normalize_date;
+use crate::cache::{FlatPage, redis_get_page, redis_store_page};
+use crate::page_data::{get_max_page_age_minutes, get_page_bytes, to_page_key};
+use crate::params::SitemapParams;
+use crate::uris::{normalize_uri, resolve_uri, to_origin};
+
+// fallbacks when robots.txt has no Sitemap lines
+const DEFAULT_SITEMAP_PATHS: [&str; 2] = ["/sitemap.xml", "/sitemap_index.xml"];
+const MAX_SITEMAPS: usize = 50;
+const MAX_SITEMAP_URLS: usize = 50000;
+const DEFAULT_URL_LIMIT: usize = 1000;
+// the sitemap protocol caps uncompressed files at 50MB
+const MAX_SITEMAP_BYTES: usize = 50 * 1024 * 1024;
+const XML_CONTENT_TYPE: &str = "application/xml";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SitemapUrl {
+  pub uri: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub lastmod: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub changefreq: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub priority: Option<f32>,
+  pub sitemap: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SitemapResultSet {
+  pub uri: String,
+  pub sitemaps: Vec<String>,
+  pub urls: Vec<SitemapUrl>,
+  // matching URLs before the limit was applied
+  pub total: usize,
+  pub valid: bool,
+}
+
+// Either child sitemaps of an index or page URLs of a url set
+#[derive(Debug, Clone, Default)]
+pub struct ParsedSitemap {
+  pub sitemaps: Vec<String>,
+  pub urls: Vec<SitemapUrl>,
+}
+
+fn child_text(node: &roxmltree::Node, name: &str) -> Option<String> {
+  node.children()
+    .find(|child| child.tag_name().name().eq_ignore_ascii_case(name))
+    .and_then(|child| child.text())
+    .map(|text| text.trim().to_owned())
+    .filter(|text| !text.is_empty())
+}
+
+// Parse a sitemap or sitemap index. Element names are matched without namespaces,
+// and plain text sitemaps with one URL per line are also accepted
+pub fn parse_sitemap(content: &str, sitemap_uri: &str) -> ParsedSitemap {
+  let mut parsed = ParsedSitemap::default();
+  let Ok(doc) = roxmltree::Document::parse(content.trim_start_matches('\u{feff}').trim()) else {
+    parsed.urls = content.lines().map(|line| line.trim())
+      .filter(|line| line.starts_with("http"))
+      .filter_map(|line| resolve_uri(line, sitemap_uri))
+      .map(|uri| SitemapUrl { uri, lastmod: None, changefreq: None, priority: None, sitemap: sitemap_uri.to_owned() })
+      .collect();
+    return parsed;
+  };
+  for node in doc.root_element().children().filter(|node| node.is_element()) {
+    let Some(loc) = child_text(&node, "loc").and_then(|loc| resolve_uri(&loc, sitemap_uri)) else {
+      continue;
+    };
+    match node.tag_name().name() {
+      "sitemap" => parsed.sitemaps.push(loc),
+      "url" => parsed.urls.push(SitemapUrl {
+        uri: loc,
+        lastmod: child_text(&node, "lastmod").and_then(|lm| normalize_date(&lm)),
+        changefreq: child_text(&node, "changefreq").map(|cf| cf.to_lowercase()),
+        priority: child_text(&node, "priority").and_then(|pr| pr.parse::<f32>().ok()),
+        sitemap: sitemap_uri.to_owned(),
+      }),
+      _ => ()
+    }
+  }
+  parsed
+}
+
+pub fn robots_sitemaps(robots_txt: &str, base_uri: &str) -> Vec<String> {
+  robots_txt.lines()
+    .filter_map(|line| line.split_once(':'))
+    .filter(|(name, _)| name.trim().eq_ignore_ascii_case("sitemap"))
+    .filter_map(|(_, value)| resolve_uri(value.trim(), base_uri))
+    .collect()
+}
+
+fn is_sitemap_uri(uri: &str) -> bool {
+  let path = uri.split(['?', '#']).next().unwrap_or("").to_lowercase();
+  path.ends_with(".xml") || path.ends_with(".xml.gz") || path.ends_with(".txt") || path.contains("sitemap")
+}
+
+// Sitemaps and robots.txt are cached decompressed, under their own keys so page fetches of the same URI
+// are neither served nor overwrite the decompressed text
+async fn fetch_text(uri: &str, skip_cache: bool) -> Option<String> {
+  let key = format!("sitemap:{}", to_page_key(uri));
+  let age_mins = if skip_cache { 1 } else { get_max_page_age_minutes() };
+  if let Some(pd) = redis_get_page(&key, Duration::minutes(age_mins)) {
+    return Some(pd.content);
+  }
+  let (bytes, content_type) = get_page_bytes(uri, MAX_SITEMAP_BYTES).await?;
+  let content = if bytes.starts_with(&[0x1f, 0x8b]) {
+    let mut text = String::new();
+    // one byte past the limit so oversized sitemaps can be rejected
+    GzDecoder::new(&bytes[..]).take(MAX_SITEMAP_BYTES as u64 + 1).read_to_string(&mut text).ok()?;
+    if text.len() > MAX_SITEMAP_BYTES {
+      return None;
+    }
+    text
+  } else {
+    String::from_utf8_lossy(&bytes).to_string()
+  };
+  let page = FlatPage::new(uri, &content, false).with_content_type(content_type.as_deref().filter(|ct| !ct.contains("gzip")).unwrap_or(XML_CONTENT_TYPE));
+  redis_store_page(&key, &page);
+  Some(content)
+}
+
+// Sitemap URIs for a site: the URI itself if it looks like a sitemap, else robots.txt entries or the usual locations
+pub async fn discover_sitemaps(uri: &str, skip_cache: bool) -> Vec<String> {
+  if is_sitemap_uri(uri) && to_origin(uri).map(|origin| origin.len() + 1 < uri.len()).unwrap_or(false) {
+    return vec![normalize_uri(uri)];
+  }
+  let Some(origin) = to_origin(uri) else {
+    return vec![];
+  };
+  let from_robots = match fetch_text(&format!("{}/robots.txt", origin), skip_cache).await {
+    Some(robots_txt) => robots_sitemaps(&robots_txt, &origin),
+    None => vec![]
+  };
+  if from_robots.is_empty() {
+    DEFAULT_SITEMAP_PATHS.iter().map(|path| format!("{}{}", origin, path)).collect()
+  } else {
+    from_robots
+  }
+}
+
+fn matches_params(item: &SitemapUrl, params: &SitemapParams) -> bool {
+  let day = |date: &str| date.chars().take(10).collect::<String>();
+  let since = params.since.as_ref().and_then(|d| normalize_date(d)).map(|d| day(&d));
+  let before = params.before.as_ref().and_then(|d| normalize_date(d)).map(|d| day(&d));
+  let lastmod = item.lastmod.as_ref().map(|lm| day(lm));
+  // URLs without lastmod are excluded once a date range is set
+  since.map(|since| lastmod.as_ref().map(|lm| *lm >= since).unwrap_or(false)).unwrap_or(true)
+    && before.map(|before| lastmod.as_ref().map(|lm| *lm < before).unwrap_or(false)).unwrap_or(true)
+    && params.pattern.as_ref().map(|pat| item.uri.pattern_match(pat, true)).unwrap_or(true)
+}
+
+// Fetch the discovered sitemaps, expanding indexes breadth-first up to MAX_SITEMAPS files
+pub async fn fetch_sitemap_urls(params: &SitemapParams) -> SitemapResultSet {
+  let uri = params.uri.clone().unwrap_or_default();
+  let skip_cache = params.skip.unwrap_or(false);
+  let mut queue: VecDeque<String> = discover_sitemaps(&uri, skip_cache).await.into();
+  let mut seen: HashSet<String> = queue.iter().cloned().collect();
+  let mut sitemaps: Vec<String> = vec![];
+  let mut seen_urls: HashSet<String> = HashSet::new();
+  let mut urls: Vec<SitemapUrl> = vec![];
+  while let Some(sitemap_uri) = queue.pop_front() {
+    if sitemaps.len() >= MAX_SITEMAPS || seen_urls.len() >= MAX_SITEMAP_URLS {
+      break;
+    }
+    let Some(content) = fetch_text(&sitemap_uri, skip_cache).await else {
+      continue;
+    };
+    let parsed = parse_sitemap(&content, &sitemap_uri);
+    if parsed.sitemaps.is_empty() && parsed.urls.is_empty() {
+      continue;
+    }
+    for child in parsed.sitemaps {
+      if seen.insert(child.clone()) {
+        queue.push_back(child);
+      }
+    }
+    for item in parsed.urls {
+      if seen_urls.len() < MAX_SITEMAP_URLS && seen_urls.insert(item.uri.clone()) && matches_params(&item, params) {
+        urls.push(item);
+      }
+    }
+    sitemaps.push(sitemap_uri);
+  }
+  let total = urls.len();
+  urls.truncate(params.limit.unwrap_or(DEFAULT_URL_LIMIT));
+  SitemapResultSet {
+    uri,
+    valid: !sitemaps.is_empty(),
+    sitemaps,
+    urls,
+    total,
+  }
+}
+
+// Most recently modified sitemap URLs, used instead of page links to seed related scans
+pub async fn sitemap_seed_uris(uri: &str, limit: usize, skip_cache: bool) -> Vec<String> {
+  let params = SitemapParams { uri: Some(uri.to_owned()), since: None, before: None, pattern: None, limit: Some(MAX_SITEMAP_URLS), skip: Some(skip_cache) };
+  let page_uri = normalize_uri(uri);
+  let mut urls = fetch_sitemap_urls(&params).await.urls;
+  // ISO dates sort chronologically as strings, and URLs without lastmod go last
+  urls.sort_by(|a, b| b.lastmod.cmp(&a.lastmod));
+  urls.into_iter().map(|item| item.uri).filter(|item_uri| *item_uri != page_uri).take(limit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::sitemap::{parse_sitemap, robots_sitemaps};
+
+  #[test]
+  fn test_sitemap_index() {
+    let index = r#"<?xml version="1.0" encoding="UTF-8"?>
+      <sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+        <sitemap><loc>https://example.com/sitemap-posts.xml.gz</loc></sitemap>
+        <sitemap><loc>/sitemap-pages.xml</loc></sitemap>
+      </sitemapindex>"#;
+    let parsed = parse_sitemap(index, "https://example.com/sitemap.xml");
+    assert_eq!(parsed.sitemaps, vec!["https://example.com/sitemap-posts.xml.gz", "https://example.com/sitemap-pages.xml"]);
+  }
+
+  #[test]
+  fn test_sitemap_urlset_fields() {
+    let urlset = r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+        <url><loc>https://example.com/about</loc><lastmod>2024-03-05</lastmod><changefreq>Monthly</changefreq><priority>0.8</priority></url>
+        <url><loc> https://example.com/news </loc></url>
+      </urlset>"#;
+    let parsed = parse_sitemap(urlset, "https://example.com/sitemap-pages.xml");
+    assert_eq!(parsed.urls.len(), 2);
+    assert_eq!(parsed.urls[0].lastmod.as_deref(), Some("2024-03-05"));
+    assert_eq!(parsed.urls[0].changefreq.as_deref(), Some("monthly"));
+    assert_eq!(parsed.urls[0].priority, Some(0.8));
+    assert_eq!(parsed.urls[1].uri, "https://example.com/news");
+  }
+
+  #[test]
+  fn test_robots_sitemaps() {
+    let robots = "User-agent: *\nDisallow: /admin\nSitemap: https://example.com/sitemap.xml\nsitemap: /news-sitemap.xml\n";
+    assert_eq!(robots_sitemaps(robots, "https://example.com"), vec!["https://example.com/sitemap.xml", "https://example.com/news-sitemap.xml"]);
+  }
+}
@@ -4,7 +4,11 @@ use serde::{Deserialize, Serialize};
 use select::predicate::{Attr, Name, Predicate};
 use select::node::Node;
 use string_patterns::*;
-use simple_string_patterns::*;
+use crate::metadata::PageMetadata;
+use crate::byline::ArticleInfo;
+use crate::language::LanguageInfo;
+use crate::regions::PageRegion;
+use crate::uris::{document_base_uri, is_same_site, normalize_uri, resolve_uri, to_origin};
 
 const MIN_MEANINFUL_TEXT_LENGTH: usize = 128;
 const MIN_MEANINFUL_TEXT_RATIO: f64 = 0.02;
@@ -104,55 +108,18 @@ pub fn loop_content_tags(node_items: &mut Vec<PageElement>, parent: &Node, depth
   text_len
 }
 
-pub fn is_local_uri(uri: &str, base_uri: &str) -> bool {
-  if uri.starts_with("/") || uri.starts_with("../") {
-    true
-  } else if uri.starts_with("http://") || uri.starts_with("https://") {
-    let mut is_local = uri.starts_with(base_uri);
-    let parts = if !is_local {
-      base_uri.to_string().to_segments(".")
-    } else {
-      vec![]
-    };
-    let num_parts = parts.len();
-    
-    if num_parts > 1 {
-      let last_part = parts.get(num_parts - 1).unwrap();
-      let first_part = parts.get(0).unwrap().to_string().to_tail("//");
-      let last_is_country_code = last_part.len() == 2;
-      // let second_last_part = parts.get(num_parts - 2).unwrap();
-      let may_have_subdomains = num_parts > 3 || first_part.as_str() == "www" || (num_parts > 2 && last_is_country_code);
-      let separator = if may_have_subdomains {
-        "."
-      } else {
-        "://"
-      };
-      let base = base_uri.to_owned().to_tail(separator);
-      let start_pattern = [r"^https?://([a-z0-9_-]+\.)?",&base].concat();
-      is_local = uri.to_owned().pattern_match(&start_pattern, true);
-    }
-    is_local
+// Relative links are always local. With include_subdomains, hosts under the same registrable domain are local too
+pub fn is_local_uri(uri: &str, base_uri: &str, include_subdomains: bool) -> bool {
+  if uri.starts_with("http://") || uri.starts_with("https://") {
+    is_same_site(uri, base_uri, include_subdomains)
   } else {
-    false
+    uri.starts_with('/') && !uri.starts_with("//") || uri.starts_with("../")
   }
 }
 
+// The page origin, or empty for URIs that do not parse, which no link is local to
 pub fn extract_base_uri(uri: &str) -> String {
-  let mut base_uri = uri.to_owned().clone();
-  if let Some((head, tail)) = uri.split_once("://") {
-    if let Some((domain, _end)) = tail.split_once("/") {
-      base_uri = vec![head,"://", domain].concat();
-    }
-  }
-  base_uri
-}
-
-pub fn concat_full_uri(uri: &str, base_uri: &str) -> String {
-  if uri.starts_with("http://") || uri.starts_with("https://") {
-    uri.to_owned()
-  } else {
-    [base_uri, uri].concat()
-  }
+  to_origin(uri).unwrap_or_default()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -164,6 +131,8 @@ pub struct PageElement {
     pub class_names: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
     #[serde(rename = "textLen")]
     pub text_len: usize,
     #[serde(rename = "linkTextLen")]
@@ -191,6 +160,7 @@ impl  PageElement {
         
         let class_opt = extract_element_attr(item, "class");
         let id_opt = extract_element_attr(item, "id");
+        let role = extract_element_attr(item, "role");
         let class_names: Vec<String> = if class_opt.is_some() { class_opt.unwrap_or("".to_owned()).split(" ").filter(|s| s.trim().len() > 0).map(|s| s.to_string()).collect::<Vec<String>>() } else { vec![] };
         
         let repl_pairs = [
@@ -207,6 +177,7 @@ impl  PageElement {
             tag_name: tag_name.to_string(),
             class_names,
             id: id_opt,
+            role,
             text_len: *text_len,
             link_text_len,
             list_links,
@@ -279,6 +250,14 @@ pub struct PageStats {
     pub num_links: usize,
     #[serde(rename="numDomainLinks")]
     pub num_domain_links: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<PageMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub article: Option<ArticleInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<LanguageInfo>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<PageRegion>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -296,6 +275,12 @@ pub struct PageOverview {
     pub num_links: usize,
     #[serde(rename="numDomainLinks")]
     pub num_domain_links: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<PageMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub article: Option<ArticleInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<LanguageInfo>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -306,7 +291,7 @@ pub enum PageOverviewResult {
 }
 
 impl PageStats {
-    pub fn new(doc: &Document, uri: &str, fetch_related_links: bool) -> PageStats {
+    pub fn new(doc: &Document, uri: &str, fetch_related_links: bool, include_subdomains: bool) -> PageStats {
         let mut elements: Vec<PageElement> = vec![];
         let base_uri = extract_base_uri(uri);
         let title: Option<String> = extract_title_from_doc(doc);
@@ -324,12 +309,15 @@ impl PageStats {
             element.set_fraction(text_len);
         }
         if fetch_related_links {
+            let page_uri = normalize_uri(uri);
+            let link_base_uri = document_base_uri(doc, uri);
             for elem in doc.find(Name("a")).into_iter() {
               if let Some(href) = extract_href_from_node(&elem) {
                 num_links += 1;
-                if is_local_uri(&href, &base_uri) && uri.starts_with('#') == false && uri.len() > 1 {
-                  if !domain_links.contains(&href) {
-                    domain_links.push(href);
+                // fragment links resolve to the page itself
+                if let Some(full_uri) = resolve_uri(&href, &link_base_uri).filter(|full_uri| *full_uri != page_uri) {
+                  if is_local_uri(&full_uri, &base_uri, include_subdomains) && !domain_links.contains(&full_uri) {
+                    domain_links.push(full_uri);
                     num_domain_links += 1;
                   }
                 }
@@ -351,6 +339,10 @@ impl PageStats {
             domain_links,
             num_links,
             num_domain_links,
+            metadata: None,
+            article: None,
+            language: None,
+            regions: vec![],
         }
     }
 
@@ -379,7 +371,34 @@ impl PageStats {
             image: self.image.clone(),
             num_links: self.num_links,
             num_domain_links: self.num_domain_links,
+            metadata: self.metadata.clone(),
+            article: self.article.clone(),
+            language: self.language.clone(),
+        }
+    }
+
+    pub fn set_metadata(&mut self, metadata: PageMetadata) {
+        if !metadata.is_empty() {
+            self.metadata = Some(metadata);
+        }
+    }
+
+    pub fn set_article(&mut self, article: ArticleInfo) {
+        if !article.is_empty() {
+            self.article = Some(article);
+        }
+    }
+
+    // Regions are classified by the caller only when requested
+    pub fn set_regions(&mut self, regions: Vec<PageRegion>) {
+        self.regions = regions;
+    }
+
+    pub fn set_language(&mut self, language: LanguageInfo) {
+        if self.lang.is_none() {
+            self.lang = language.declared.clone();
         }
+        self.language = Some(language);
     }
 
     pub fn to_result(&self, full: bool) -> PageOverviewResult {
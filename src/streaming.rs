@@ -0,0 +1,96 @@
+use std::convert::Infallible;
+use axum::{
+  body::StreamBody,
+  http::{header, HeaderMap, HeaderValue},
+  response::{IntoResponse, Response, sse::{Event, KeepAlive, Sse}},
+};
+use futures::{Future, StreamExt, stream};
+use tokio::sync::mpsc::{self, Sender};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+// One streamed result. Over SSE `event` is the event name, over NDJSON each line is the whole object
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamEvent {
+  pub event: String,
+  pub data: Value,
+}
+
+// events buffered for a slow client before the producer waits
+const STREAM_BUFFER: usize = 8;
+
+// Sends events to the client. `send` waits while the buffer is full and resolves to false
+// once the client has disconnected, so producers can stop early
+#[derive(Clone)]
+pub struct EventSender {
+  tx: Sender<StreamEvent>,
+}
+
+impl EventSender {
+  // The data is serialized straight away, so the returned future does not borrow it
+  pub fn send<T: Serialize>(&self, event: &str, data: &T) -> impl Future<Output = bool> {
+    let tx = self.tx.clone();
+    let event = StreamEvent { event: event.to_owned(), data: json!(data) };
+    async move { tx.send(event).await.is_ok() }
+  }
+}
+
+// Serialized JSON never contains raw newlines, so each event is exactly one line
+fn to_ndjson_line(event: &StreamEvent) -> String {
+  format!("{}\n", json!(event))
+}
+
+fn wants_event_stream(headers: &HeaderMap) -> bool {
+  headers.get(header::ACCEPT)
+    .and_then(|accept| accept.to_str().ok())
+    .map(|accept| accept.contains("text/event-stream"))
+    .unwrap_or(false)
+}
+
+// Run the producer in the background and stream its events as Server-Sent Events
+// when the client accepts text/event-stream, otherwise as newline-delimited JSON
+pub fn stream_response<F, Fut>(headers: &HeaderMap, producer: F) -> Response
+where
+  F: FnOnce(EventSender) -> Fut,
+  Fut: Future<Output = ()> + Send + 'static,
+{
+  let (tx, rx) = mpsc::channel::<StreamEvent>(STREAM_BUFFER);
+  tokio::spawn(producer(EventSender { tx }));
+  let rx = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|ev| (ev, rx)) });
+  if wants_event_stream(headers) {
+    let events = rx.map(|ev| Ok::<Event, Infallible>(Event::default().event(ev.event).json_data(ev.data).unwrap_or_default()));
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+  } else {
+    let lines = rx.map(|ev| Ok::<String, Infallible>(to_ndjson_line(&ev)));
+    (
+      [(header::CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"))],
+      StreamBody::new(lines)
+    ).into_response()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::http::{header, HeaderMap, HeaderValue};
+  use serde_json::{json, Value};
+  use crate::streaming::{StreamEvent, to_ndjson_line, wants_event_stream};
+
+  #[test]
+  fn test_accept_header_selects_event_stream() {
+    let mut headers = HeaderMap::new();
+    assert!(!wants_event_stream(&headers));
+    headers.insert(header::ACCEPT, HeaderValue::from_static("application/json, text/event-stream;q=0.9"));
+    assert!(wants_event_stream(&headers));
+  }
+
+  #[test]
+  fn test_ndjson_line_escapes_newlines() {
+    let event = StreamEvent { event: "page".to_owned(), data: json!({ "bestText": "line one\nline two", "valid": true }) };
+    let line = to_ndjson_line(&event);
+    assert!(line.ends_with('\n'));
+    assert_eq!(line.matches('\n').count(), 1);
+    let parsed: Value = serde_json::from_str(line.trim_end()).unwrap();
+    assert_eq!(parsed["event"], "page");
+    assert_eq!(parsed["data"]["bestText"], "line one\nline two");
+  }
+}
@@ -0,0 +1,148 @@
+use serde::Serialize;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use string_patterns::*;
+use crate::page_data::to_typed_value;
+use crate::params::{TargetConfig, TargetKind};
+use crate::pdf::PDF_CONTENT_TYPE;
+use crate::xpath::{evaluate_xpath, parse_xml};
+
+// How a response body is interpreted, from its media type or by sniffing the body
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentKind {
+  Html,
+  Json,
+  Xml,
+  Text,
+  Pdf,
+}
+
+impl ContentKind {
+  pub fn detect(content_type: &str, content: &str) -> ContentKind {
+    let media_type = content_type.split(';').next().unwrap_or("").trim();
+    if media_type == PDF_CONTENT_TYPE {
+      ContentKind::Pdf
+    } else if media_type.contains("json") {
+      ContentKind::Json
+    } else if media_type.contains("html") {
+      // APIs sometimes send JSON as text/html
+      sniff_json(content).unwrap_or(ContentKind::Html)
+    } else if media_type.contains("xml") {
+      ContentKind::Xml
+    } else if media_type.starts_with("text/") {
+      ContentKind::Text
+    } else {
+      let start = content.trim_start_matches('\u{feff}').trim_start();
+      sniff_json(start).unwrap_or(if start.starts_with("<?xml") { ContentKind::Xml } else { ContentKind::Html })
+    }
+  }
+}
+
+fn sniff_json(content: &str) -> Option<ContentKind> {
+  let start = content.trim_start();
+  ((start.starts_with('{') || start.starts_with('[')) && serde_json::from_str::<Value>(start).is_ok()).then_some(ContentKind::Json)
+}
+
+fn to_text(value: &Value) -> String {
+  match value {
+    Value::String(s) => s.to_owned(),
+    _ => value.to_string()
+  }
+}
+
+// Keep JSON values as they are unless the target kind asks for a scalar type
+fn to_typed_json(value: &Value, kind: Option<TargetKind>) -> Value {
+  match (value, kind) {
+    (Value::String(s), _) => to_typed_value(s, kind),
+    (_, Some(TargetKind::Integer | TargetKind::Float | TargetKind::Boolean)) => to_typed_value(&value.to_string(), kind),
+    _ => value.clone()
+  }
+}
+
+// A parsed JSON, XML or plain text body that targets are evaluated against.
+// Paths are JSONPath for JSON and XPath for XML, while text targets match lines with `pattern`.
+// XPath elements in a default namespace take the `ns:` prefix, as in //ns:entry/ns:title
+pub enum StructuredSource {
+  Json(Value),
+  Xml(String),
+  Text(String),
+}
+
+impl StructuredSource {
+  pub fn new(kind: ContentKind, content: &str) -> Option<StructuredSource> {
+    match kind {
+      ContentKind::Json => serde_json::from_str(content).ok().map(StructuredSource::Json),
+      ContentKind::Xml => Some(StructuredSource::Xml(content.to_owned())),
+      ContentKind::Text => Some(StructuredSource::Text(content.to_owned())),
+      _ => None
+    }
+  }
+
+  pub fn select(&self, path: &str) -> Vec<Value> {
+    match self {
+      StructuredSource::Json(data) => match JsonPath::parse(path) {
+        Ok(json_path) => json_path.query(data).all().into_iter().cloned().collect(),
+        Err(_) => vec![]
+      },
+      StructuredSource::Xml(xml) => match parse_xml(xml) {
+        Some(package) => evaluate_xpath(&package.as_document(), path).into_iter().map(Value::String).collect(),
+        None => vec![]
+      },
+      StructuredSource::Text(text) => text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).map(|line| Value::String(line.to_owned())).collect(),
+    }
+  }
+
+  // Matches for an item config, filtered by its pattern and typed by its kind
+  pub fn select_item(&self, path: &str, item: &TargetConfig) -> Vec<Value> {
+    let re_opt = item.pattern.as_ref().and_then(|pat| build_regex(pat, true).ok());
+    self.select(path).iter()
+      .filter(|value| re_opt.as_ref().map(|re| re.is_match(&to_text(value))).unwrap_or(true))
+      .map(|value| to_typed_json(value, item.kind))
+      .collect()
+  }
+
+  pub fn select_text(&self, path: &str) -> String {
+    self.select(path).iter().map(to_text).collect::<Vec<String>>().join("\n")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+  use crate::params::TargetConfig;
+  use crate::structured::{ContentKind, StructuredSource};
+
+  fn products() -> StructuredSource {
+    StructuredSource::new(ContentKind::Json, r#"{"products": [{"name": "Kettle", "price": "£24.50", "tags": ["home"]}, {"name": "Toaster", "price": "£31"}]}"#).unwrap()
+  }
+
+  #[test]
+  fn test_detect_content_kind() {
+    assert_eq!(ContentKind::detect("application/json; charset=utf-8", "{}"), ContentKind::Json);
+    assert_eq!(ContentKind::detect("text/html", "[1, 2]"), ContentKind::Json);
+    assert_eq!(ContentKind::detect("text/html", "<html></html>"), ContentKind::Html);
+    assert_eq!(ContentKind::detect("application/octet-stream", "<?xml version=\"1.0\"?><a/>"), ContentKind::Xml);
+    assert_eq!(ContentKind::detect("text/plain", "hello"), ContentKind::Text);
+  }
+
+  #[test]
+  fn test_json_path_select() {
+    let source = products();
+    assert_eq!(source.select("$.products[*].name"), vec![json!("Kettle"), json!("Toaster")]);
+    assert_eq!(source.select("$.products[0].tags"), vec![json!(["home"])]);
+  }
+
+  #[test]
+  fn test_json_item_pattern_and_kind() {
+    let item: TargetConfig = serde_json::from_value(json!({ "path": "$.products[*].price", "kind": "float", "pattern": "^£2" })).unwrap();
+    assert_eq!(products().select_item("$.products[*].price", &item), vec![json!(24.5)]);
+  }
+
+  #[test]
+  fn test_text_item_matches_blocks() {
+    let text = StructuredSource::new(ContentKind::Text, "alpha: 1\n\nbeta: 2\n").unwrap();
+    let item: TargetConfig = serde_json::from_value(json!({ "pattern": "^beta" })).unwrap();
+    assert_eq!(text.select_item("", &item), vec![json!("beta: 2")]);
+  }
+}
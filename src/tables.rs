@@ -0,0 +1,241 @@
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use string_patterns::*;
+use crate::page_data::to_typed_value;
+use crate::params::TargetKind;
+use crate::cleantext::collapse_whitespace;
+
+// plain numbers with optional sign, currency symbol, thousands separators and percent sign
+const NUMERIC_CELL_PATTERN: &str = r"^[+\-]?\s*[$€£¥]?\s*[+\-]?(\d{1,3}(,\d{3})+|\d+)(\.\d+)?\s*%?$";
+// guard against malformed spans producing huge grids
+const MAX_SPAN: usize = 1000;
+
+#[derive(Debug, Clone)]
+struct GridCell {
+  text: String,
+  is_header: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TableData {
+  pub index: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub caption: Option<String>,
+  pub headers: Vec<String>,
+  pub rows: Vec<Map<String, Value>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub csv: Option<String>,
+}
+
+fn cell_text(elem: &ElementRef) -> String {
+  collapse_whitespace(&elem.text().collect::<Vec<&str>>().join(" "))
+}
+
+fn span_attr(elem: &ElementRef, name: &str) -> usize {
+  elem.value().attr(name).and_then(|v| v.trim().parse::<usize>().ok()).unwrap_or(1).clamp(1, MAX_SPAN)
+}
+
+fn child_elements<'a>(elem: &ElementRef<'a>) -> Vec<ElementRef<'a>> {
+  elem.children().filter_map(ElementRef::wrap).collect()
+}
+
+// Rows of this table only, excluding rows of nested tables, flagged if they sit in a thead
+fn collect_rows<'a>(table: &ElementRef<'a>) -> Vec<(ElementRef<'a>, bool)> {
+  let mut rows = vec![];
+  for child in child_elements(table) {
+    match child.value().name() {
+      "tr" => rows.push((child, false)),
+      "thead" | "tbody" | "tfoot" => {
+        let in_head = child.value().name() == "thead";
+        for row in child_elements(&child).into_iter().filter(|el| el.value().name() == "tr") {
+          rows.push((row, in_head));
+        }
+      },
+      _ => ()
+    }
+  }
+  rows
+}
+
+// Expand colspan and rowspan so every row has one cell per column
+fn build_grid(rows: &[(ElementRef, bool)]) -> Vec<Vec<Option<GridCell>>> {
+  let mut grid: Vec<Vec<Option<GridCell>>> = vec![vec![]; rows.len()];
+  for (row_index, (row, in_head)) in rows.iter().enumerate() {
+    let mut col = 0;
+    for cell in child_elements(row).into_iter().filter(|el| matches!(el.value().name(), "td" | "th")) {
+      while grid[row_index].get(col).map(|c| c.is_some()).unwrap_or(false) {
+        col += 1;
+      }
+      let grid_cell = GridCell {
+        text: cell_text(&cell),
+        is_header: *in_head || cell.value().name() == "th",
+      };
+      let colspan = span_attr(&cell, "colspan");
+      let rowspan = span_attr(&cell, "rowspan").min(rows.len() - row_index);
+      for target_row in grid.iter_mut().skip(row_index).take(rowspan) {
+        if target_row.len() < col + colspan {
+          target_row.resize(col + colspan, None);
+        }
+        for target in target_row.iter_mut().skip(col).take(colspan) {
+          *target = Some(grid_cell.clone());
+        }
+      }
+      col += colspan;
+    }
+  }
+  grid
+}
+
+fn count_header_rows(rows: &[(ElementRef, bool)], grid: &[Vec<Option<GridCell>>]) -> usize {
+  let thead_rows = rows.iter().take_while(|(_, in_head)| *in_head).count();
+  if thead_rows > 0 {
+    thead_rows
+  } else {
+    grid.iter().take_while(|row| !row.is_empty() && row.iter().all(|c| c.as_ref().map(|gc| gc.is_header).unwrap_or(true))).count()
+  }
+}
+
+// Combine stacked header rows per column and make every key unique
+fn resolve_headers(header_rows: &[Vec<Option<GridCell>>], num_cols: usize) -> Vec<String> {
+  let mut headers: Vec<String> = vec![];
+  for col in 0..num_cols {
+    let mut parts: Vec<String> = vec![];
+    for row in header_rows {
+      if let Some(Some(cell)) = row.get(col) {
+        if !cell.text.is_empty() && !parts.contains(&cell.text) {
+          parts.push(cell.text.clone());
+        }
+      }
+    }
+    let mut name = if parts.is_empty() { format!("column_{}", col + 1) } else { parts.join(" / ") };
+    if headers.contains(&name) {
+      let mut suffix = 2;
+      while headers.contains(&format!("{}_{}", name, suffix)) {
+        suffix += 1;
+      }
+      name = format!("{}_{}", name, suffix);
+    }
+    headers.push(name);
+  }
+  headers
+}
+
+pub fn to_cell_value(text: &str) -> Value {
+  if text.pattern_match(NUMERIC_CELL_PATTERN, true) {
+    to_typed_value(text, Some(TargetKind::Float))
+  } else {
+    Value::String(text.to_string())
+  }
+}
+
+fn escape_csv_field(text: &str) -> String {
+  if text.contains([',', '"', '\n', '\r']) {
+    ["\"", &text.replace('"', "\"\""), "\""].concat()
+  } else {
+    text.to_string()
+  }
+}
+
+pub fn to_csv(headers: &[String], rows: &[Map<String, Value>]) -> String {
+  let mut lines = vec![headers.iter().map(|h| escape_csv_field(h)).collect::<Vec<String>>().join(",")];
+  for row in rows {
+    let fields = headers.iter().map(|h| match row.get(h) {
+      Some(Value::String(s)) => escape_csv_field(s),
+      Some(Value::Number(n)) => n.as_f64().map(|f| f.to_string()).unwrap_or(n.to_string()),
+      Some(Value::Null) | None => "".to_string(),
+      Some(v) => v.to_string(),
+    }).collect::<Vec<String>>();
+    lines.push(fields.join(","));
+  }
+  lines.join("\r\n")
+}
+
+pub fn extract_table(table: &ElementRef, index: usize, with_csv: bool) -> TableData {
+  let rows = collect_rows(table);
+  let grid = build_grid(&rows);
+  let num_cols = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+  let num_header_rows = count_header_rows(&rows, &grid);
+  let headers = resolve_headers(&grid[..num_header_rows], num_cols);
+  let records = grid[num_header_rows..].iter()
+    .filter(|row| row.iter().any(|c| c.as_ref().map(|gc| !gc.text.is_empty()).unwrap_or(false)))
+    .map(|row| {
+      let mut record = Map::new();
+      for (col, header) in headers.iter().enumerate() {
+        let value = match row.get(col) {
+          Some(Some(cell)) if !cell.text.is_empty() => to_cell_value(&cell.text),
+          _ => Value::Null
+        };
+        record.insert(header.clone(), value);
+      }
+      record
+    }).collect::<Vec<Map<String, Value>>>();
+  let caption = child_elements(table).into_iter().find(|el| el.value().name() == "caption").map(|el| cell_text(&el)).filter(|c| !c.is_empty());
+  let csv = if with_csv { Some(to_csv(&headers, &records)) } else { None };
+  TableData {
+    index,
+    id: table.value().attr("id").map(|id| id.to_string()),
+    caption,
+    headers,
+    rows: records,
+    csv,
+  }
+}
+
+// Extract all tables or those matched by a CSS selector
+pub fn extract_tables(html_obj: &Html, selector_str: Option<&str>, with_csv: bool) -> Vec<TableData> {
+  let mut tables: Vec<TableData> = vec![];
+  if let Ok(selector) = Selector::parse(selector_str.unwrap_or("table")) {
+    let table_sel = Selector::parse("table").unwrap();
+    for elem in html_obj.select(&selector) {
+      // a selector may match a wrapper, so use the tables inside it
+      let matched = if elem.value().name() == "table" { vec![elem] } else { elem.select(&table_sel).collect() };
+      for table in matched {
+        let index = tables.len();
+        tables.push(extract_table(&table, index, with_csv));
+      }
+    }
+  }
+  tables
+}
+
+#[cfg(test)]
+mod tests {
+  use scraper::Html;
+  use serde_json::json;
+  use crate::tables::{TableData, extract_tables};
+
+  fn oil_table() -> TableData {
+    let html = Html::parse_fragment(r#"<table id="oiltb"><thead><tr><th rowspan="2">Grade</th><th colspan="2">Price</th></tr><tr><th>USD</th><th>EUR</th></tr></thead>
+      <tbody><tr><td>Brent</td><td>1,234.50</td><td>80%</td></tr><tr><td rowspan="2">WTI</td><td>70</td><td>n/a</td></tr><tr><td>71</td><td>"x", y</td></tr></tbody></table>"#);
+    let mut tables = extract_tables(&html, Some("#oiltb"), true);
+    assert_eq!(tables.len(), 1);
+    tables.remove(0)
+  }
+
+  #[test]
+  fn test_spanned_headers_are_joined() {
+    assert_eq!(oil_table().headers, vec!["Grade", "Price / USD", "Price / EUR"]);
+  }
+
+  #[test]
+  fn test_numeric_cells_are_parsed() {
+    let table = oil_table();
+    assert_eq!(table.rows[0].get("Price / USD"), Some(&json!(1234.5)));
+    assert_eq!(table.rows[0].get("Price / EUR"), Some(&json!(80.0)));
+    assert_eq!(table.rows[1].get("Price / EUR"), Some(&json!("n/a")));
+  }
+
+  #[test]
+  fn test_rowspan_fills_following_rows() {
+    assert_eq!(oil_table().rows[2].get("Grade"), Some(&json!("WTI")));
+  }
+
+  #[test]
+  fn test_csv_quotes_cells() {
+    let csv = oil_table().csv.unwrap_or_default();
+    assert!(csv.ends_with("WTI,71,\"\"\"x\"\", y\""));
+  }
+}
@@ -0,0 +1,168 @@
+use scraper::{Html, Selector};
+use select::document::Document;
+use select::predicate::{Attr, Name, Predicate};
+use url::{Url, form_urlencoded};
+
+// query parameters added by campaign, click and newsletter trackers
+const TRACKING_PARAM_PREFIXES: [&str; 3] = ["utm_", "mc_", "_hs"];
+const TRACKING_PARAMS: [&str; 16] = [
+  "fbclid", "gclid", "gclsrc", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "igshid", "twclid",
+  "_ga", "_gl", "mkt_tok", "oly_anon_id", "oly_enc_id", "vero_id",
+];
+
+fn is_tracking_param(name: &str) -> bool {
+  let name = name.to_lowercase();
+  TRACKING_PARAMS.contains(&name.as_str()) || TRACKING_PARAM_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+// Strip fragments and tracking parameters. The parser already lowercases hosts,
+// drops default ports and resolves dot segments
+pub fn normalize_url(url: &mut Url) {
+  url.set_fragment(None);
+  let Some(query) = url.query().map(|q| q.to_owned()) else {
+    return;
+  };
+  // keep the remaining raw key=value segments rather than re-encoding them
+  let segments = query.split('&').collect::<Vec<&str>>();
+  let kept = segments.iter()
+    .filter(|segment| !form_urlencoded::parse(segment.as_bytes()).next().map(|(name, _)| is_tracking_param(&name)).unwrap_or(false))
+    .copied()
+    .collect::<Vec<&str>>();
+  if kept.len() == segments.len() {
+    return;
+  }
+  if kept.iter().all(|segment| segment.is_empty()) {
+    url.set_query(None);
+  } else {
+    url.set_query(Some(&kept.join("&")));
+  }
+}
+
+pub fn normalize_uri(uri: &str) -> String {
+  match Url::parse(uri.trim()) {
+    Ok(mut url) => {
+      normalize_url(&mut url);
+      url.to_string()
+    },
+    Err(_) => uri.to_owned()
+  }
+}
+
+// Resolve a reference against the page or base URL following RFC 3986.
+// Only web links are returned, so mailto:, javascript: and data: references yield None
+pub fn resolve_uri(href: &str, base_uri: &str) -> Option<String> {
+  let base = Url::parse(base_uri).ok()?;
+  let mut url = base.join(href.trim()).ok()?;
+  if !matches!(url.scheme(), "http" | "https") {
+    return None;
+  }
+  normalize_url(&mut url);
+  Some(url.to_string())
+}
+
+// scheme, host and port only
+pub fn to_origin(uri: &str) -> Option<String> {
+  Url::parse(uri.trim()).ok().map(|url| url.origin().ascii_serialization()).filter(|origin| origin != "null")
+}
+
+pub fn to_host(uri: &str) -> Option<String> {
+  Url::parse(uri.trim()).ok().and_then(|url| url.host_str().map(|host| host.trim_end_matches('.').to_lowercase()))
+}
+
+// The domain a registrant controls, e.g. bbc.co.uk for news.bbc.co.uk, according to the Public Suffix List
+pub fn registrable_domain(host: &str) -> Option<String> {
+  psl::domain_str(host).map(|domain| domain.to_lowercase())
+}
+
+fn strip_www(host: &str) -> &str {
+  host.strip_prefix("www.").unwrap_or(host)
+}
+
+// Same site if registrable domains match, or, when subdomains count as external, if hosts match ignoring www.
+// Hosts without a registrable domain such as IP addresses and localhost must match exactly
+pub fn is_same_site(uri: &str, base_uri: &str, include_subdomains: bool) -> bool {
+  match (to_host(uri), to_host(base_uri)) {
+    (Some(host), Some(base_host)) => {
+      if !include_subdomains {
+        strip_www(&host) == strip_www(&base_host)
+      } else {
+        match (registrable_domain(&host), registrable_domain(&base_host)) {
+          (Some(domain), Some(base_domain)) => domain == base_domain,
+          _ => host == base_host
+        }
+      }
+    },
+    _ => false
+  }
+}
+
+fn effective_base(base_href: Option<&str>, uri: &str) -> String {
+  base_href.and_then(|href| Url::parse(uri).ok()?.join(href.trim()).ok())
+    .map(|url| url.to_string())
+    .unwrap_or(uri.to_owned())
+}
+
+// The URL relative links resolve against, honouring <base href>
+pub fn document_base_uri(doc: &Document, uri: &str) -> String {
+  let base_href = doc.find(Name("base").and(Attr("href", ()))).next().and_then(|el| el.attr("href"));
+  effective_base(base_href, uri)
+}
+
+pub fn html_base_uri(html_obj: &Html, uri: &str) -> String {
+  let sel = Selector::parse("base[href]").unwrap();
+  let base_href = html_obj.select(&sel).next().and_then(|el| el.value().attr("href"));
+  effective_base(base_href, uri)
+}
+
+#[cfg(test)]
+mod tests {
+  use select::document::Document;
+  use crate::uris::{resolve_uri, document_base_uri, normalize_uri};
+  use crate::stats::is_local_uri;
+
+  const PAGE: &str = "https://Example.com:443/news/world/story.html?id=5#top";
+
+  #[test]
+  fn test_resolve_relative_uris() {
+    assert_eq!(resolve_uri("../sport/", PAGE).as_deref(), Some("https://example.com/news/sport/"));
+    assert_eq!(resolve_uri("//cdn.host/x.js", PAGE).as_deref(), Some("https://cdn.host/x.js"));
+    assert_eq!(resolve_uri("?q=1", PAGE).as_deref(), Some("https://example.com/news/world/story.html?q=1"));
+  }
+
+  #[test]
+  fn test_resolve_strips_tracking_and_fragments() {
+    assert_eq!(resolve_uri("/a?utm_source=x&b=2&fbclid=y#frag", PAGE).as_deref(), Some("https://example.com/a?b=2"));
+  }
+
+  #[test]
+  fn test_resolve_rejects_non_http_schemes() {
+    assert_eq!(resolve_uri("mailto:me@example.com", PAGE), None);
+  }
+
+  #[test]
+  fn test_normalize_uri() {
+    assert_eq!(normalize_uri("HTTP://WWW.Example.com:80/?gclid=1"), "http://www.example.com/");
+    assert_eq!(normalize_uri("https://example.com/s?q=a%20b&path=/x&flag"), "https://example.com/s?q=a%20b&path=/x&flag");
+    assert_eq!(normalize_uri("https://example.com/s?q=a%20b&utm_medium=email&flag"), "https://example.com/s?q=a%20b&flag");
+  }
+
+  #[test]
+  fn test_resolve_against_base_href() {
+    let doc = Document::from(r#"<html><head><base href="/docs/v2/" /></head><body></body></html>"#);
+    let base = document_base_uri(&doc, PAGE);
+    assert_eq!(resolve_uri("intro.html", &base).as_deref(), Some("https://example.com/docs/v2/intro.html"));
+  }
+
+  #[test]
+  fn test_local_uris() {
+    let base = "https://www.bbc.co.uk";
+    assert!(is_local_uri("https://news.bbc.co.uk/world", base, true));
+    assert!(!is_local_uri("https://news.bbc.co.uk/world", base, false));
+    assert!(is_local_uri("https://bbc.co.uk/", base, false));
+    assert!(!is_local_uri("https://itv.co.uk/", base, true));
+    assert!(is_local_uri("/sport", base, false));
+    assert!(is_local_uri("https://shop.example.com.au/", "https://example.com.au", true));
+    assert!(!is_local_uri("https://other.com.au/", "https://example.com.au", true));
+    assert!(!is_local_uri("https://example.com/", "https://example.com.au", true));
+  }
+}
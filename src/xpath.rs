@@ -0,0 +1,162 @@
+use scraper::{ElementRef, Html, Node as HtmlNode};
+use sxd_document::{Package, dom::{Document, Element, ParentOfChild}, parser};
+use sxd_xpath::{Context, Factory, Value as XPathValue, nodeset::Node};
+
+const DEFAULT_NS_PREFIX: &str = "ns";
+
+pub fn parse_xml(xml: &str) -> Option<Package> {
+  parser::parse(xml.trim_start_matches('\u{feff}').trim()).ok()
+}
+
+// Evaluate an XPath 1.0 expression, returning the string value of each matched node
+// or the single result of expressions yielding strings, numbers or booleans
+pub fn evaluate_xpath(doc: &Document, expr: &str) -> Vec<String> {
+  match evaluate(doc, expr) {
+    Some(XPathValue::Nodeset(nodes)) => nodes.document_order().iter().map(|node| node.string_value().trim().to_owned()).collect(),
+    Some(value) => vec![value.string()],
+    None => vec![]
+  }
+}
+
+// Prefixes declared on the root element are available to expressions, and a default namespace
+// is bound to DEFAULT_NS_PREFIX, e.g. //ns:entry/ns:title in Atom feeds or //ns:url/ns:loc in sitemaps
+fn namespace_context<'d>(doc: &Document<'d>) -> Context<'d> {
+  let mut context = Context::new();
+  let root_element = doc.root().children().into_iter().find_map(|child| child.element());
+  if let Some(root) = root_element {
+    for ns in root.namespaces_in_scope() {
+      context.set_namespace(ns.prefix(), ns.uri());
+    }
+    if let Some(uri) = root.default_namespace_uri().filter(|_| root.namespace_uri_for_prefix(DEFAULT_NS_PREFIX).is_none()) {
+      context.set_namespace(DEFAULT_NS_PREFIX, uri);
+    }
+  }
+  context
+}
+
+fn evaluate<'d>(doc: &Document<'d>, expr: &str) -> Option<XPathValue<'d>> {
+  let xpath = Factory::new().build(expr).ok()??;
+  xpath.evaluate(&namespace_context(doc), doc.root()).ok()
+}
+
+// Copy elements, attributes and text of an HTML tree into an XPath document.
+// Comments and doctypes are skipped, so element positions match in both trees
+fn mirror_element<'d>(doc: &Document<'d>, source: ElementRef) -> Element<'d> {
+  let element = doc.create_element(source.value().name());
+  for (name, value) in source.value().attrs() {
+    element.set_attribute_value(name, value);
+  }
+  for child in source.children() {
+    match child.value() {
+      HtmlNode::Element(_) => if let Some(child_ref) = ElementRef::wrap(child) {
+        element.append_child(mirror_element(doc, child_ref));
+      },
+      HtmlNode::Text(text) => element.append_child(doc.create_text(text)),
+      _ => ()
+    }
+  }
+  element
+}
+
+// Positions among sibling elements from below the root element down to this element
+fn element_path(element: Element) -> Vec<usize> {
+  let mut path = vec![];
+  let mut current = element;
+  while let Some(ParentOfChild::Element(parent)) = current.parent() {
+    let position = parent.children().iter().filter_map(|child| child.element()).position(|el| el == current).unwrap_or(0);
+    path.push(position);
+    current = parent;
+  }
+  path.reverse();
+  path
+}
+
+fn find_element<'a>(root: ElementRef<'a>, path: &[usize]) -> Option<ElementRef<'a>> {
+  path.iter().try_fold(root, |current, position| current.children().filter_map(ElementRef::wrap).nth(*position))
+}
+
+// An HTML document mirrored once for XPath, so each item's expression is evaluated against the same copy
+pub struct HtmlXPathDocument {
+  html: Html,
+  package: Package,
+}
+
+impl HtmlXPathDocument {
+  pub fn new(html: Html) -> Self {
+    let package = Package::new();
+    let doc = package.as_document();
+    doc.root().append_child(mirror_element(&doc, html.root_element()));
+    HtmlXPathDocument { html, package }
+  }
+
+  // Matched elements are rendered by `render` as with CSS selectors,
+  // while attributes, text nodes and scalar results yield strings
+  pub fn extract<F>(&self, expr: &str, render: F) -> Vec<String>
+  where F: Fn(ElementRef) -> Option<String> {
+    let doc = self.package.as_document();
+    let root = self.html.root_element();
+    match evaluate(&doc, expr) {
+      Some(XPathValue::Nodeset(nodes)) => nodes.document_order().iter().filter_map(|node| match node {
+        Node::Element(element) => find_element(root, &element_path(*element)).and_then(&render),
+        _ => Some(node.string_value().trim().to_owned())
+      }).collect(),
+      Some(value) => vec![value.string()],
+      None => vec![]
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use scraper::Html;
+  use crate::page_data::render_element;
+  use crate::params::ExtractMode;
+  use crate::xpath::{HtmlXPathDocument, evaluate_xpath, parse_xml};
+
+  #[test]
+  fn test_xml_xpath() {
+    let package = parse_xml(r#"<?xml version="1.0"?><catalog><book id="b1"><title>Dune</title><price>9.99</price></book>
+      <book id="b2"><title>Emma</title><price>4.50</price></book></catalog>"#).unwrap();
+    let doc = package.as_document();
+    assert_eq!(evaluate_xpath(&doc, "//book/title"), vec!["Dune", "Emma"]);
+    assert_eq!(evaluate_xpath(&doc, "//book[price < 5]/@id"), vec!["b2"]);
+    assert_eq!(evaluate_xpath(&doc, "count(//book)"), vec!["2"]);
+  }
+
+  #[test]
+  fn test_invalid_xpath_is_empty() {
+    let package = parse_xml(r#"<?xml version="1.0"?><catalog><book id="b1"/></catalog>"#).unwrap();
+    assert!(evaluate_xpath(&package.as_document(), "//[").is_empty());
+  }
+
+  #[test]
+  fn test_xml_xpath_namespaces() {
+    let atom = parse_xml(r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:media="http://search.yahoo.com/mrss/">
+      <entry><title>Post</title><media:thumbnail url="https://example.com/t.jpg"/></entry></feed>"#).unwrap();
+    let doc = atom.as_document();
+    assert_eq!(evaluate_xpath(&doc, "//ns:entry/ns:title"), vec!["Post"]);
+    assert_eq!(evaluate_xpath(&doc, "//media:thumbnail/@url"), vec!["https://example.com/t.jpg"]);
+  }
+
+  fn price_list() -> HtmlXPathDocument {
+    HtmlXPathDocument::new(Html::parse_document(r#"<!doctype html><html><body><!-- list --><ul class="prices">
+      <li data-sku="k1">Kettle <b>£24.50</b></li><li data-sku="t2">Toaster <b>£31</b></li></ul></body></html>"#))
+  }
+
+  #[test]
+  fn test_html_xpath() {
+    let html = price_list();
+    assert_eq!(html.extract("//li[2]/b", |el| Some(el.html())), vec!["<b>£31</b>"]);
+    assert_eq!(html.extract("/html/body/ul/li/@data-sku", |_| None), vec!["k1", "t2"]);
+    assert_eq!(html.extract("//li[contains(., 'Kettle')]/b/text()", |_| None), vec!["£24.50"]);
+    assert_eq!(html.extract("count(//ul[@class='prices']/li)", |_| None), vec!["2"]);
+  }
+
+  #[test]
+  fn test_html_xpath_renders_elements() {
+    let html = price_list();
+    assert_eq!(html.extract("//li", |el| render_element(el, None, ExtractMode::Text)), vec!["Kettle £24.50", "Toaster £31"]);
+    assert_eq!(html.extract("//li", |el| render_element(el, Some("data-sku"), ExtractMode::Text)), vec!["k1", "t2"]);
+    assert_eq!(html.extract("//ul", |el| render_element(el, Some("id"), ExtractMode::Text)), Vec::<String>::new());
+  }
+}